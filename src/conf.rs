@@ -1,6 +1,8 @@
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::env;
+use std::path::Path;
 use std::sync::RwLock;
 
 #[derive(Debug, Deserialize)]
@@ -13,6 +15,30 @@ pub struct Settings {
     pub elastic_index: String,
     pub listen_address: String,
     pub fb2c_path: String,
+    pub library_path: String,
+    pub covers_cache: String,
+    pub bulk_docs: usize,
+    pub bulk_bytes: usize,
+    pub bulk_parallelism: usize,
+    pub bulk_retries: u32,
+    #[serde(default)]
+    pub libraries: BTreeMap<String, Library>,
+}
+
+// DEFAULT_LIBRARY is the name of the library used when no libraries are configured
+pub const DEFAULT_LIBRARY: &str = "default";
+
+// Library is the INPX collection with its own index and the directory of the book containers,
+// the relative directories are resolved against the library_path
+#[derive(Debug, Deserialize, Clone)]
+pub struct Library {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub inpx: String,
+    pub index: String,
+    #[serde(default)]
+    pub path: String,
 }
 
 lazy_static::lazy_static! {
@@ -29,6 +55,12 @@ impl Settings {
             .set_default("elastic_login", "admin")?
             .set_default("listen_address", "localhost:3000")?
             .set_default("fb2c_path", "./fb2c")?
+            .set_default("library_path", ".")?
+            .set_default("covers_cache", "covers")?
+            .set_default("bulk_docs", 5000)?
+            .set_default("bulk_bytes", 10 * 1024 * 1024)?
+            .set_default("bulk_parallelism", 4)?
+            .set_default("bulk_retries", 5)?
             .add_source(File::with_name("flibooks").required(false))
             .add_source(
                 File::with_name(env::var("FLI_CONFIG").unwrap_or_default().as_str())
@@ -39,4 +71,29 @@ impl Settings {
 
         s.try_deserialize()
     }
+
+    // libraries returns the configured libraries, or the single default one
+    // searched via the elastic_index alias
+    pub fn libraries(&self) -> Vec<Library> {
+        if self.libraries.is_empty() {
+            return vec![Library {
+                name: DEFAULT_LIBRARY.to_string(),
+                inpx: String::new(),
+                index: self.elastic_index.clone(),
+                path: self.library_path.clone(),
+            }];
+        }
+
+        self.libraries
+            .iter()
+            .map(|(name, library)| Library {
+                name: name.clone(),
+                path: Path::new(&self.library_path)
+                    .join(&library.path)
+                    .to_string_lossy()
+                    .into_owned(),
+                ..library.clone()
+            })
+            .collect()
+    }
 }
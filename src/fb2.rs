@@ -0,0 +1,387 @@
+// FB2 books metadata: the annotation, the keywords, the translators, the publisher info
+// and the cover reference from the book's description, and the cover image itself.
+// The books are often encoded in windows-1251, the encoding is taken from the XML declaration.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::error::Error;
+use std::io::BufRead;
+
+const TITLE_INFO: &str = "FictionBook/description/title-info";
+const PUBLISH_INFO: &str = "FictionBook/description/publish-info";
+// INLINE are the annotation elements inside the paragraphs
+const INLINE: [&str; 8] = [
+    "emphasis",
+    "strong",
+    "style",
+    "a",
+    "strikethrough",
+    "sub",
+    "sup",
+    "code",
+];
+
+// Description is the metadata of the book missing from the INPX
+#[derive(Default)]
+pub struct Description {
+    pub annotation: String,
+    pub keywords: Vec<String>,
+    pub translators: Vec<String>,
+    pub publisher: String,
+    pub year: String,
+    pub isbn: String,
+    // cover is the id of the cover image binary
+    pub cover: String,
+}
+
+impl Description {
+    // publication_year returns the year of the publish-info, it's often like "2001 г."
+    pub fn publication_year(&self) -> Option<i32> {
+        let digits: String = self
+            .year
+            .chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        match digits.parse() {
+            Ok(year) if digits.len() == 4 => Some(year),
+            _ => None,
+        }
+    }
+}
+
+// Cover is the cover image of the book
+pub struct Cover {
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+// Person is the name of the translator, the nickname is used when the name is missing
+#[derive(Default)]
+struct Person {
+    first: String,
+    middle: String,
+    last: String,
+    nickname: String,
+}
+
+impl Person {
+    fn name(&self) -> String {
+        let name = [&self.first, &self.middle, &self.last]
+            .iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        if name.is_empty() {
+            self.nickname.trim().to_string()
+        } else {
+            name
+        }
+    }
+}
+
+// parse_description reads the description of the book, the rest of the book isn't read
+pub fn parse_description<R: BufRead>(input: R) -> Result<Description, Box<dyn Error>> {
+    let mut reader = Reader::from_reader(input);
+    let mut buf = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+
+    let mut desc = Description::default();
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut paragraph = String::new();
+    let mut keywords = String::new();
+    let mut translator = Person::default();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                if name == "image" {
+                    cover_href(&reader, &e, &path, &mut desc)?;
+                }
+                path.push(name);
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"image" => {
+                cover_href(&reader, &e, &path, &mut desc)?;
+            }
+            Event::End(_) => {
+                let current = path.join("/");
+                let name = path.pop().unwrap_or_default();
+                if current.starts_with(&format!("{}/annotation", TITLE_INFO))
+                    && !INLINE.contains(&name.as_str())
+                {
+                    // the annotation paragraphs, poems and subtitles are separated by the new lines
+                    add_paragraph(&mut paragraphs, &mut paragraph);
+                } else if current == format!("{}/translator", TITLE_INFO) {
+                    let name = translator.name();
+                    if !name.is_empty() {
+                        desc.translators.push(name);
+                    }
+                    translator = Person::default();
+                } else if name == "description" {
+                    break;
+                }
+            }
+            Event::Text(t) => {
+                let text = t.unescape()?;
+                add_text(
+                    &path.join("/"),
+                    &text,
+                    &mut paragraph,
+                    &mut keywords,
+                    &mut translator,
+                    &mut desc,
+                );
+            }
+            Event::CData(t) => {
+                let text = reader.decoder().decode(&t)?;
+                add_text(
+                    &path.join("/"),
+                    &text,
+                    &mut paragraph,
+                    &mut keywords,
+                    &mut translator,
+                    &mut desc,
+                );
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    desc.annotation = paragraphs.join("\n");
+    desc.keywords = keywords
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(String::from)
+        .collect();
+    desc.publisher = desc.publisher.trim().to_string();
+    desc.year = desc.year.trim().to_string();
+    desc.isbn = desc.isbn.trim().to_string();
+    Ok(desc)
+}
+
+// cover_image extracts the coverpage image from the base64 encoded binaries at the end
+// of the book, None means the book has no cover
+pub fn cover_image(content: &[u8]) -> Result<Option<Cover>, Box<dyn Error>> {
+    let cover = parse_description(content)?.cover;
+    if cover.is_empty() {
+        return Ok(None);
+    }
+
+    let mut reader = Reader::from_reader(content);
+    // the sloppy markup of the book's body shouldn't prevent getting to the binaries
+    reader.config_mut().check_end_names = false;
+    let mut buf = Vec::new();
+    // content type of the cover binary while it's being read
+    let mut content_type: Option<String> = None;
+    let mut data = String::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) if e.local_name().as_ref() == b"binary" => {
+                let mut id = String::new();
+                let mut kind = String::new();
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    let value = attr.decode_and_unescape_value(reader.decoder())?;
+                    match attr.key.local_name().as_ref() {
+                        b"id" => id = value.to_string(),
+                        b"content-type" => kind = value.to_string(),
+                        _ => {}
+                    }
+                }
+                if id == cover {
+                    content_type = Some(kind);
+                }
+            }
+            Event::Text(t) if content_type.is_some() => {
+                data.push_str(&reader.decoder().decode(&t)?);
+            }
+            Event::End(e) if e.local_name().as_ref() == b"binary" => {
+                if let Some(content_type) = content_type.take() {
+                    data.retain(|c| !c.is_whitespace());
+                    let data = STANDARD.decode(&data)?;
+                    return Ok(Some(Cover { content_type, data }));
+                }
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+// add_text appends the text to the description field the current element belongs to
+fn add_text(
+    path: &str,
+    text: &str,
+    paragraph: &mut String,
+    keywords: &mut String,
+    translator: &mut Person,
+    desc: &mut Description,
+) {
+    if path.starts_with(&format!("{}/annotation", TITLE_INFO)) {
+        paragraph.push_str(text);
+    } else if let Some(field) = path.strip_prefix(&format!("{}/", TITLE_INFO)) {
+        match field {
+            "keywords" => keywords.push_str(text),
+            "translator/first-name" => translator.first.push_str(text),
+            "translator/middle-name" => translator.middle.push_str(text),
+            "translator/last-name" => translator.last.push_str(text),
+            "translator/nickname" => translator.nickname.push_str(text),
+            _ => {}
+        }
+    } else if let Some(field) = path.strip_prefix(&format!("{}/", PUBLISH_INFO)) {
+        match field {
+            "publisher" => desc.publisher.push_str(text),
+            "year" => desc.year.push_str(text),
+            "isbn" => desc.isbn.push_str(text),
+            _ => {}
+        }
+    }
+}
+
+// add_paragraph adds the collected paragraph text with the whitespaces collapsed
+fn add_paragraph(paragraphs: &mut Vec<String>, paragraph: &mut String) {
+    let text = paragraph
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if !text.is_empty() {
+        paragraphs.push(text);
+    }
+    paragraph.clear();
+}
+
+// cover_href takes the id of the first coverpage image, the link is like "#cover.jpg"
+fn cover_href<R>(
+    reader: &Reader<R>,
+    e: &BytesStart,
+    path: &[String],
+    desc: &mut Description,
+) -> Result<(), Box<dyn Error>> {
+    if !desc.cover.is_empty() || path.join("/") != format!("{}/coverpage", TITLE_INFO) {
+        return Ok(());
+    }
+    for attr in e.attributes() {
+        let attr = attr?;
+        if attr.key.local_name().as_ref() == b"href" {
+            let href = attr.decode_and_unescape_value(reader.decoder())?;
+            desc.cover = href.trim_start_matches('#').to_string();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // windows_1251 encodes the Russian text the way the older FB2 books are stored
+    fn windows_1251(text: &str) -> Vec<u8> {
+        text.chars()
+            .map(|c| match c {
+                'А'..='я' => (c as u32 - 'А' as u32 + 0xC0) as u8,
+                'ё' => 0xB8,
+                'Ё' => 0xA8,
+                _ => c as u8,
+            })
+            .collect()
+    }
+
+    const BOOK: &str = r##"<?xml version="1.0" encoding="windows-1251"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+<description>
+  <title-info>
+    <genre>prose_classic</genre>
+    <book-title>Война и мир</book-title>
+    <annotation>
+      <p>Роман-эпопея   <emphasis>Льва</emphasis> Толстого &amp; всё.</p>
+      <empty-line/>
+      <p>Второй
+         абзац.</p>
+    </annotation>
+    <keywords>роман, война ,, 1812</keywords>
+    <coverpage><image l:href="#cover.jpg"/></coverpage>
+    <translator><first-name>Иван</first-name><last-name>Петров</last-name></translator>
+    <translator><nickname>Ёжик</nickname></translator>
+  </title-info>
+  <publish-info>
+    <publisher>АСТ</publisher>
+    <year> 2001 </year>
+    <isbn>5-17-000000-0</isbn>
+  </publish-info>
+</description>
+<body><p>Текст<p></body>
+<binary id="other.jpg" content-type="image/jpeg">AAAA</binary>
+<binary id="cover.jpg" content-type="image/png">aGVs
+bG8=</binary>
+</FictionBook>"##;
+
+    #[test]
+    fn parse_description_windows_1251() {
+        let desc = parse_description(windows_1251(BOOK).as_slice()).unwrap();
+
+        assert_eq!(
+            desc.annotation,
+            "Роман-эпопея Льва Толстого & всё.\nВторой абзац."
+        );
+        assert_eq!(desc.keywords, vec!["роман", "война", "1812"]);
+        assert_eq!(desc.translators, vec!["Иван Петров", "Ёжик"]);
+        assert_eq!(desc.publisher, "АСТ");
+        assert_eq!(desc.year, "2001");
+        assert_eq!(desc.isbn, "5-17-000000-0");
+        assert_eq!(desc.cover, "cover.jpg");
+    }
+
+    #[test]
+    fn parse_description_without_metadata() {
+        let book = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+            <FictionBook><description><title-info><book-title>Книга</book-title>\
+            </title-info></description><body><p>Текст</p></body></FictionBook>";
+        let desc = parse_description(book.as_bytes()).unwrap();
+
+        assert_eq!(desc.annotation, "");
+        assert!(desc.keywords.is_empty());
+        assert!(desc.translators.is_empty());
+        assert_eq!(desc.cover, "");
+        assert_eq!(desc.publication_year(), None);
+    }
+
+    #[test]
+    fn publication_year_digits() {
+        let year = |year: &str| {
+            Description {
+                year: year.to_string(),
+                ..Default::default()
+            }
+            .publication_year()
+        };
+        assert_eq!(year("2001"), Some(2001));
+        assert_eq!(year("2001 г."), Some(2001));
+        assert_eq!(year("М., 1999"), Some(1999));
+        assert_eq!(year("01"), None);
+        assert_eq!(year("неизвестен"), None);
+    }
+
+    #[test]
+    fn cover_image_from_binary() {
+        // the body has the unclosed paragraph before the binaries
+        let cover = cover_image(windows_1251(BOOK).as_slice()).unwrap().unwrap();
+        assert_eq!(cover.content_type, "image/png");
+        assert_eq!(cover.data, b"hello");
+    }
+
+    #[test]
+    fn cover_image_missing() {
+        let book = "<FictionBook><description><title-info></title-info></description>\
+            <binary id=\"cover.jpg\" content-type=\"image/jpeg\">AAAA</binary></FictionBook>";
+        assert!(cover_image(book.as_bytes()).unwrap().is_none());
+    }
+}
@@ -1,5 +1,6 @@
 mod conf;
 mod convert;
+mod fb2;
 mod logger;
 mod parse;
 mod serve;
@@ -19,17 +20,101 @@ async fn main() {
                         .short('i')
                         .long("inpx")
                         .value_name("INPX_FILE")
-                        .help("Flibooks backup index file to be parsed")
-                        .required(true),
+                        .help("Flibooks backup index file to be parsed, defaults to the library's one"),
+                )
+                .arg(
+                    clap::Arg::new("library")
+                        .short('l')
+                        .long("library")
+                        .value_name("LIBRARY")
+                        .help("Library to import, all the configured libraries by default"),
+                )
+                .arg(
+                    clap::Arg::new("incremental")
+                        .long("incremental")
+                        .help("Update the existing index: add new, update changed and remove missing books")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::Arg::new("append")
+                        .long("append")
+                        .help("Add the books to the existing index without removing any, e.g. to import the rejects file")
+                        .conflicts_with("incremental")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::Arg::new("index")
+                        .long("index")
+                        .value_name("INDEX")
+                        .help("Append to the given version of the index and switch the alias to it, e.g. after the rejected import")
+                        .requires("append"),
+                )
+                .arg(
+                    clap::Arg::new("rejects")
+                        .short('r')
+                        .long("rejects")
+                        .value_name("REJECTS_FILE")
+                        .help("INPX file to save the books rejected by the bulk indexing"),
+                )
+                .arg(
+                    clap::Arg::new("allow-rejects")
+                        .long("allow-rejects")
+                        .help("Switch the alias to the new index even if some books were rejected")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::Arg::new("delete-old")
+                        .long("delete-old")
+                        .help("Delete the previous versions of the index after the alias switch")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::Arg::new("fb2")
+                        .long("fb2")
+                        .help("Read the FB2 books from the library's containers to index their annotations")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("init-index")
+                .about("Creates the books index with the explicit mapping")
+                .arg(
+                    clap::Arg::new("library")
+                        .short('l')
+                        .long("library")
+                        .value_name("LIBRARY")
+                        .help("Library to create the index for, all the configured libraries by default"),
                 ),
         )
         .subcommand(clap::Command::new("serve").about("Serves the REST API (Default)"))
         .get_matches();
 
-    match matches.subcommand_matches("parse") {
-        Some(parse_args) => {
-            let inpx_file = parse_args.get_one::<String>("inpx").unwrap();
-            parse::start(inpx_file).await.unwrap();
+    match matches.subcommand() {
+        Some(("parse", parse_args)) => {
+            let inpx_file = parse_args.get_one::<String>("inpx");
+            let library = parse_args.get_one::<String>("library");
+            let opts = parse::ParseOptions {
+                incremental: parse_args.get_flag("incremental"),
+                append: parse_args.get_flag("append"),
+                index: parse_args.get_one::<String>("index").cloned(),
+                rejects_file: parse_args.get_one::<String>("rejects").cloned(),
+                allow_rejects: parse_args.get_flag("allow-rejects"),
+                delete_old: parse_args.get_flag("delete-old"),
+                fb2: parse_args.get_flag("fb2"),
+            };
+            parse::start(
+                library.map(String::as_str),
+                inpx_file.map(String::as_str),
+                &opts,
+            )
+            .await
+            .unwrap();
+        }
+        Some(("init-index", init_args)) => {
+            let library = init_args.get_one::<String>("library");
+            parse::init_index(library.map(String::as_str))
+                .await
+                .unwrap();
         }
         _ => {
             serve::start().await.unwrap();
@@ -0,0 +1,286 @@
+use log::{debug, error, warn};
+use serde_json::Value;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::conf;
+use crate::parse::indexer::{is_overloaded, Indexer};
+
+// BulkQueue splits the bulk actions into chunks bounded by the documents count
+// and the body size, and sends up to `bulk_parallelism` chunks concurrently
+pub(crate) struct BulkQueue {
+    indexer: Arc<Indexer>,
+    entries: Vec<BulkEntry>,
+    bytes: usize,
+    max_docs: usize,
+    max_bytes: usize,
+    permits: Arc<Semaphore>,
+    tasks: JoinSet<Vec<InpxRecord>>,
+    rejected: Vec<InpxRecord>,
+}
+
+// BulkEntry is the ndjson action with its document, kept separately so the items
+// rejected by the overloaded cluster could be sent again
+struct BulkEntry {
+    body: String,
+    record: Option<InpxRecord>,
+}
+
+// InpxRecord is the original INPX line of the queued book, kept to report
+// and save the books rejected by the bulk API
+pub(crate) struct InpxRecord {
+    pub inp: String,
+    pub container: String,
+    pub file: String,
+    pub line: String,
+}
+
+impl BulkQueue {
+    pub fn new(indexer: Arc<Indexer>) -> Result<Self, Box<dyn Error>> {
+        let settings = conf::SETTINGS.read()?;
+
+        Ok(BulkQueue {
+            indexer,
+            entries: Vec::new(),
+            bytes: 0,
+            max_docs: settings.bulk_docs.max(1),
+            max_bytes: settings.bulk_bytes,
+            permits: Arc::new(Semaphore::new(settings.bulk_parallelism.max(1))),
+            tasks: JoinSet::new(),
+            rejected: Vec::new(),
+        })
+    }
+
+    // push adds the action with its optional document source and INPX line to the queue
+    pub async fn push(
+        &mut self,
+        action: &Value,
+        doc: Option<&Value>,
+        record: Option<InpxRecord>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut entry = serde_json::to_string(action)?;
+        entry.push('\n');
+        if let Some(doc) = doc {
+            entry.push_str(&serde_json::to_string(doc)?);
+            entry.push('\n');
+        }
+
+        if self.entries.len() >= self.max_docs || self.bytes + entry.len() > self.max_bytes {
+            self.flush().await?;
+        }
+
+        self.bytes += entry.len();
+        self.entries.push(BulkEntry {
+            body: entry,
+            record,
+        });
+        Ok(())
+    }
+
+    // flush sends the collected chunk, waits while there are too many chunks in flight
+    pub async fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+
+        let entries = std::mem::take(&mut self.entries);
+        self.bytes = 0;
+        let permit = self.permits.clone().acquire_owned().await?;
+        let indexer = self.indexer.clone();
+
+        self.tasks.spawn(async move {
+            let rejected = send_chunk(&indexer, entries).await;
+            drop(permit);
+            rejected
+        });
+
+        while let Some(res) = self.tasks.try_join_next() {
+            self.rejected.append(&mut res?);
+        }
+        Ok(())
+    }
+
+    // finish sends the rest of the queue, waits for all the chunks to complete
+    // and returns the INPX records of the books which failed to be indexed
+    pub async fn finish(mut self) -> Result<Vec<InpxRecord>, Box<dyn Error>> {
+        self.flush().await?;
+        while let Some(res) = self.tasks.join_next().await {
+            self.rejected.append(&mut res?);
+        }
+        Ok(self.rejected)
+    }
+}
+
+// send_chunk sends the chunk and resends its items rejected by the overloaded cluster
+// with the exponential backoff, returns the INPX records of the books failed to be indexed
+async fn send_chunk(indexer: &Indexer, mut entries: Vec<BulkEntry>) -> Vec<InpxRecord> {
+    let mut rejected = Vec::new();
+    let mut attempt = 0;
+
+    loop {
+        let docs = entries.len();
+        let body: String = entries.iter().map(|e| e.body.as_str()).collect();
+        let result = match indexer.bulk(body).await {
+            Ok(result) => result,
+            Err(e) => {
+                error!("Bulk of {} documents failed: {}", docs, e);
+                rejected.extend(entries.into_iter().filter_map(|e| e.record));
+                return rejected;
+            }
+        };
+        let items = result["items"].as_array().map_or(0, Vec::len);
+        if !result["errors"].as_bool().unwrap_or(false) && items == docs {
+            debug!("Successfully indexed bulk of {} documents", docs);
+            return rejected;
+        }
+
+        let retry = failed_entries(&result, entries, &mut rejected);
+        if retry.is_empty() {
+            return rejected;
+        }
+        match indexer.retry_delay(attempt) {
+            Some(delay) => {
+                warn!(
+                    "{} bulk items rejected by the overloaded cluster, retrying in {:?}",
+                    retry.len(),
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                entries = retry;
+            }
+            None => {
+                error!(
+                    "{} bulk items are still rejected by the overloaded cluster",
+                    retry.len()
+                );
+                rejected.extend(retry.into_iter().filter_map(|e| e.record));
+                return rejected;
+            }
+        }
+    }
+}
+
+// failed_entries logs the failed items of the bulk response and adds their INPX records
+// to the rejected ones, the items rejected by the overloaded cluster are returned to be
+// sent again. The response items go in the same order as the actions of the request,
+// the actions without the items are rejected.
+fn failed_entries(
+    result: &Value,
+    mut entries: Vec<BulkEntry>,
+    rejected: &mut Vec<InpxRecord>,
+) -> Vec<BulkEntry> {
+    let items = match result["items"].as_array() {
+        Some(items) => items,
+        None => {
+            error!("Bulk indexing had errors, but no items were returned");
+            rejected.extend(entries.into_iter().filter_map(|e| e.record));
+            return Vec::new();
+        }
+    };
+
+    if items.len() < entries.len() {
+        // the actions missing from the response are unknown to be indexed
+        let missing = entries.split_off(items.len());
+        error!(
+            "Bulk response has {} items for {} actions, rejecting the unmatched ones",
+            items.len(),
+            items.len() + missing.len()
+        );
+        rejected.extend(missing.into_iter().filter_map(|e| e.record));
+    }
+
+    let mut retry = Vec::new();
+    for (item, entry) in items.iter().zip(entries) {
+        let (action, status) = match item.as_object().and_then(|o| o.iter().next()) {
+            Some(first) => first,
+            None => continue,
+        };
+        let err = match status.get("error") {
+            Some(err) => err,
+            None => continue,
+        };
+        if is_overloaded(status["status"].as_u64().unwrap_or(0) as u16) {
+            retry.push(entry);
+            continue;
+        }
+
+        let reason = format!(
+            "{}: {}",
+            err["type"].as_str().unwrap_or("unknown"),
+            err["reason"].as_str().unwrap_or("")
+        );
+        match entry.record {
+            Some(r) => {
+                error!(
+                    "Failed to index '{}' of '{}': {}",
+                    r.file, r.container, reason
+                );
+                rejected.push(r);
+            }
+            None => error!("Failed to {} '{}': {}", action, status["_id"], reason),
+        }
+    }
+    retry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(file: &str) -> BulkEntry {
+        BulkEntry {
+            body: String::new(),
+            record: Some(InpxRecord {
+                inp: "fb2-000001-000100.inp".to_string(),
+                container: "fb2-000001-000100.zip".to_string(),
+                file: file.to_string(),
+                line: String::new(),
+            }),
+        }
+    }
+
+    #[test]
+    fn failed_entries_statuses() {
+        let result = json!({"errors": true, "items": [
+            {"index": {"_id": "1", "status": 201}},
+            {"index": {"_id": "2", "status": 429, "error": {"type": "es_rejected_execution_exception"}}},
+            {"index": {"_id": "3", "status": 400, "error": {"type": "mapper_parsing_exception"}}},
+        ]});
+        let mut rejected = Vec::new();
+        let retry = failed_entries(
+            &result,
+            vec![entry("1"), entry("2"), entry("3")],
+            &mut rejected,
+        );
+
+        let retried: Vec<&str> = retry
+            .iter()
+            .filter_map(|e| e.record.as_ref())
+            .map(|r| r.file.as_str())
+            .collect();
+        assert_eq!(retried, vec!["2"]);
+        let files: Vec<&str> = rejected.iter().map(|r| r.file.as_str()).collect();
+        assert_eq!(files, vec!["3"]);
+    }
+
+    #[test]
+    fn failed_entries_missing_items() {
+        let result = json!({"errors": true, "items": [
+            {"index": {"_id": "1", "status": 400, "error": {"type": "mapper_parsing_exception"}}},
+        ]});
+        let mut rejected = Vec::new();
+        let retry = failed_entries(
+            &result,
+            vec![entry("1"), entry("2"), entry("3")],
+            &mut rejected,
+        );
+
+        assert!(retry.is_empty());
+        let files: Vec<&str> = rejected.iter().map(|r| r.file.as_str()).collect();
+        assert_eq!(files, vec!["2", "3", "1"]);
+    }
+}
@@ -0,0 +1,412 @@
+use log::warn;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+use crate::conf;
+use crate::conf::Library;
+use crate::parse::mapping;
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+// MAX_BACKOFF_EXPONENT caps the retry delay at RETRY_BASE_DELAY * 2^10, i.e. about 8.5 minutes
+const MAX_BACKOFF_EXPONENT: u32 = 10;
+
+// is_overloaded tells the statuses of the requests and the bulk items rejected
+// by the overloaded cluster, those are worth retrying
+pub(crate) fn is_overloaded(status: u16) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS.as_u16()
+        || status == StatusCode::SERVICE_UNAVAILABLE.as_u16()
+}
+
+pub(crate) struct Indexer {
+    client: reqwest::Client,
+    pub url: String,
+    login: String,
+    password: String,
+    // alias is the name the books are searched by, index is the one the books are written to
+    pub alias: String,
+    pub index: String,
+    retries: u32,
+}
+
+impl Indexer {
+    pub fn new(library: &Library) -> Result<Self, Box<dyn Error>> {
+        let settings = conf::SETTINGS.read()?;
+
+        Ok(Indexer {
+            client: reqwest::Client::new(),
+            url: settings.elastic_url.clone(),
+            login: settings.elastic_login.clone(),
+            password: settings.elastic_password.clone(),
+            alias: library.index.clone(),
+            index: library.index.clone(),
+            retries: settings.bulk_retries,
+        })
+    }
+
+    // index_exists checks whether the index or alias with the given name exists
+    pub async fn index_exists(&self, index: &str) -> Result<bool, Box<dyn Error>> {
+        let index_url = format!("{}/{}", self.url, index);
+        let response = self
+            .client
+            .head(&index_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .send()
+            .await?;
+        Ok(response.status().is_success())
+    }
+
+    // create_index creates the books index with the explicit mapping unless it already exists,
+    // returns whether the index was created
+    pub async fn create_index(&self, index: &str) -> Result<bool, Box<dyn Error>> {
+        if self.index_exists(index).await? {
+            return Ok(false);
+        }
+
+        let index_url = format!("{}/{}", self.url, index);
+        let response = self
+            .client
+            .put(&index_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .json(&mapping::index_definition())
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(true)
+        } else {
+            let body = response.text().await?;
+            Err(format!(
+                "Failed to create the '{}' index: {} - {}",
+                index, status, body
+            )
+            .into())
+        }
+    }
+
+    // alias_indices returns the indices the alias currently points to
+    pub async fn alias_indices(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let alias_url = format!("{}/_alias/{}", self.url, self.alias);
+        let response = self
+            .client
+            .get(&alias_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let body: Value = response.error_for_status()?.json().await?;
+
+        Ok(body
+            .as_object()
+            .map(|indices| indices.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    // version_indices returns all the versioned indices of the alias, i.e. `<alias>-<date>`
+    pub async fn version_indices(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let cat_url = format!(
+            "{}/_cat/indices/{}-*?h=index&format=json",
+            self.url, self.alias
+        );
+        let body: Value = self
+            .client
+            .get(&cat_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let prefix = format!("{}-", self.alias);
+        Ok(body
+            .as_array()
+            .map(|indices| {
+                indices
+                    .iter()
+                    .filter_map(|i| i["index"].as_str())
+                    .filter(|name| {
+                        name.strip_prefix(&prefix)
+                            .is_some_and(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+                    })
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    // switch_alias atomically moves the alias from the old indices to the current one
+    pub async fn switch_alias(&self, old_indices: &[String]) -> Result<(), Box<dyn Error>> {
+        let mut actions: Vec<Value> = old_indices
+            .iter()
+            .map(|old| json!({"remove": {"index": old, "alias": self.alias}}))
+            .collect();
+        actions.push(json!({"add": {"index": self.index, "alias": self.alias}}));
+
+        let aliases_url = format!("{}/_aliases", self.url);
+        self.client
+            .post(&aliases_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .json(&json!({"actions": actions}))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // set_meta stores the import metadata in the mapping of the index
+    pub async fn set_meta(&self, meta: &Value) -> Result<(), Box<dyn Error>> {
+        let mapping_url = format!("{}/{}/_mapping", self.url, self.index);
+        self.client
+            .put(&mapping_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .json(&json!({"_meta": meta}))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // refresh makes all the indexed books visible for search
+    pub async fn refresh(&self) -> Result<(), Box<dyn Error>> {
+        let refresh_url = format!("{}/{}/_refresh", self.url, self.index);
+        self.client
+            .post(&refresh_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn delete_index(&self, index: &str) -> Result<(), Box<dyn Error>> {
+        let index_url = format!("{}/{}", self.url, index);
+        self.client
+            .delete(&index_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // bulk posts the ndjson body to the _bulk API and returns the response, the request
+    // is retried with exponential backoff while the cluster is overloaded or unreachable
+    pub async fn bulk(&self, bulk: String) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let bulk_url = format!("{}/_bulk", self.url);
+        let mut attempt = 0;
+
+        loop {
+            let sent = self
+                .client
+                .post(&bulk_url)
+                .basic_auth(&self.login, Some(&self.password))
+                .header(CONTENT_TYPE, "application/x-ndjson")
+                .body(bulk.clone())
+                .send()
+                .await;
+            let response = match sent {
+                Ok(response) => response,
+                Err(e) => match self.retry_delay(attempt) {
+                    Some(delay) => {
+                        warn!("Bulk request failed: {}, retrying in {:?}", e, delay);
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    None => return Err(e.into()),
+                },
+            };
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response.json().await?);
+            }
+
+            if is_overloaded(status.as_u16()) {
+                if let Some(delay) = self.retry_delay(attempt) {
+                    warn!(
+                        "Bulk request rejected with {}, retrying in {:?}",
+                        status, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    continue;
+                }
+            }
+
+            let body = response.text().await?;
+            return Err(format!("Error processing bulk: {} - {}", status, body).into());
+        }
+    }
+
+    // retry_delay returns the backoff delay before the next attempt, None when
+    // the attempts are exhausted
+    pub fn retry_delay(&self, attempt: u32) -> Option<Duration> {
+        if attempt < self.retries {
+            Some(RETRY_BASE_DELAY * 2u32.pow(attempt.min(MAX_BACKOFF_EXPONENT)))
+        } else {
+            None
+        }
+    }
+
+    // container_books returns the ids and sources of all the indexed books of the container
+    pub async fn container_books(
+        &self,
+        container: &str,
+    ) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+        let mut books = HashMap::new();
+
+        let search_url = format!("{}/{}/_search?scroll=1m", self.url, self.index);
+        let response = self
+            .client
+            .post(&search_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .json(&json!({
+                "size": 5000,
+                "query": {
+                    "term": {
+                        "container": container
+                    }
+                }
+            }))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            // nothing is indexed yet
+            return Ok(books);
+        }
+        let mut body: Value = response.error_for_status()?.json().await?;
+
+        loop {
+            let hits = body["hits"]["hits"].as_array().cloned().unwrap_or_default();
+            if hits.is_empty() {
+                break;
+            }
+            for hit in hits {
+                if let Some(id) = hit["_id"].as_str() {
+                    books.insert(id.to_string(), hit["_source"].clone());
+                }
+            }
+
+            let scroll_url = format!("{}/_search/scroll", self.url);
+            body = self
+                .client
+                .post(&scroll_url)
+                .basic_auth(&self.login, Some(&self.password))
+                .json(&json!({
+                    "scroll": "1m",
+                    "scroll_id": body["_scroll_id"],
+                }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+        }
+
+        if let Some(scroll_id) = body["_scroll_id"].as_str() {
+            let scroll_url = format!("{}/_search/scroll", self.url);
+            self.client
+                .delete(&scroll_url)
+                .basic_auth(&self.login, Some(&self.password))
+                .json(&json!({"scroll_id": scroll_id}))
+                .send()
+                .await?;
+        }
+
+        Ok(books)
+    }
+
+    // indexed_containers returns the names of all the containers present in the index
+    pub async fn indexed_containers(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let search_url = format!("{}/{}/_search", self.url, self.index);
+        let response = self
+            .client
+            .post(&search_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .json(&json!({
+                "size": 0,
+                "aggs": {
+                    "container": {
+                        "terms": {
+                            "field": "container",
+                            "size": 100000
+                        }
+                    }
+                }
+            }))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let body: Value = response.error_for_status()?.json().await?;
+
+        Ok(body["aggregations"]["container"]["buckets"]
+            .as_array()
+            .map(|buckets| {
+                buckets
+                    .iter()
+                    .filter_map(|b| b["key"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    // delete_container removes all the books of the container, returns the number of removed books
+    pub async fn delete_container(&self, container: &str) -> Result<u64, Box<dyn Error>> {
+        let delete_url = format!("{}/{}/_delete_by_query", self.url, self.index);
+        let body: Value = self
+            .client
+            .post(&delete_url)
+            .basic_auth(&self.login, Some(&self.password))
+            .json(&json!({
+                "query": {
+                    "term": {
+                        "container": container
+                    }
+                }
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(body["deleted"].as_u64().unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_backoff() {
+        let indexer = Indexer {
+            client: reqwest::Client::new(),
+            url: String::new(),
+            login: String::new(),
+            password: String::new(),
+            alias: String::new(),
+            index: String::new(),
+            retries: 40,
+        };
+        assert_eq!(indexer.retry_delay(0), Some(RETRY_BASE_DELAY));
+        assert_eq!(indexer.retry_delay(3), Some(RETRY_BASE_DELAY * 8));
+        // the large attempts numbers neither overflow nor grow the delay further
+        assert_eq!(indexer.retry_delay(10), Some(RETRY_BASE_DELAY * 1024));
+        assert_eq!(indexer.retry_delay(39), Some(RETRY_BASE_DELAY * 1024));
+        assert_eq!(indexer.retry_delay(40), None);
+    }
+}
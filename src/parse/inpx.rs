@@ -0,0 +1,325 @@
+use log::warn;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Read, Seek};
+use zip::ZipArchive;
+
+pub const STRUCTURE_INFO: &str = "structure.info";
+const COLLECTION_INFO: &str = "collection.info";
+const VERSION_INFO: &str = "version.info";
+
+// DEFAULT_STRUCTURE is the Flibusta fields layout used when the INPX has no structure.info
+const DEFAULT_STRUCTURE: &str =
+    "AUTHOR;GENRE;TITLE;SERIES;SERNO;FILE;SIZE;LIBID;DEL;EXT;DATE;LANG;LIBRATE;KEYWORDS;";
+
+// REQUIRED_FIELDS must be present in every INPX line, the rest of the fields are optional
+const REQUIRED_FIELDS: [&str; 3] = ["AUTHOR", "TITLE", "FILE"];
+
+// InpxStructure maps the INPX field names to their positions in the line
+pub(crate) struct InpxStructure {
+    pub layout: String,
+    positions: HashMap<String, usize>,
+    min_fields: usize,
+}
+
+impl InpxStructure {
+    pub fn new(layout: &str) -> Result<Self, String> {
+        let positions: HashMap<String, usize> = layout
+            .trim()
+            .split(';')
+            .map(|f| f.trim().to_uppercase())
+            .enumerate()
+            .filter(|(_, f)| !f.is_empty())
+            .map(|(i, f)| (f, i))
+            .collect();
+
+        let mut min_fields = 0;
+        for name in REQUIRED_FIELDS {
+            match positions.get(name) {
+                Some(pos) => min_fields = min_fields.max(pos + 1),
+                None => return Err(format!("INPX structure has no {} field", name)),
+            }
+        }
+
+        Ok(InpxStructure {
+            layout: layout.trim().to_string(),
+            positions,
+            min_fields,
+        })
+    }
+
+    // read loads the structure.info of the INPX archive, falls back to the default layout
+    pub fn read<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Self, Box<dyn Error>> {
+        match read_info(archive, STRUCTURE_INFO)? {
+            Some(layout) => Ok(InpxStructure::new(&layout)?),
+            None => Ok(InpxStructure::new(DEFAULT_STRUCTURE)?),
+        }
+    }
+
+    fn field<'a>(&self, fields: &[&'a str], name: &str) -> &'a str {
+        self.positions
+            .get(name)
+            .and_then(|&pos| fields.get(pos))
+            .map(|f| f.trim())
+            .unwrap_or("")
+    }
+
+    // process_book converts the INPX line into the book document, the books are stored
+    // in the container named after the .inp file unless the line has the FOLDER field
+    pub fn process_book(&self, line: &str, container: &str) -> Option<Value> {
+        let fields: Vec<&str> = line.split('\x04').collect();
+        if fields.len() < self.min_fields {
+            warn!(
+                "Skipping malformed line of '{}': expected at least {} fields, got {}",
+                container,
+                self.min_fields,
+                fields.len()
+            );
+            return None;
+        }
+
+        let list = |name: &str| -> Vec<&str> {
+            self.field(&fields, name)
+                .split(':')
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+        let optional = |name: &str| Some(self.field(&fields, name)).filter(|v| !v.is_empty());
+
+        let keywords: Vec<&str> = self
+            .field(&fields, "KEYWORDS")
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect();
+        let authors: Vec<Value> = list("AUTHOR")
+            .into_iter()
+            .filter_map(parse_author)
+            .collect();
+        let rate = optional("LIBRATE")
+            .or_else(|| optional("RATE"))
+            .and_then(|r| r.parse::<i32>().ok());
+
+        Some(json!({
+            "title": self.field(&fields, "TITLE"),
+            "authors": authors.iter().map(|a| a["name"].clone()).collect::<Vec<Value>>(),
+            "author_details": authors,
+            "author_sort": authors.first().map(|a| a["sort"].clone()),
+            "genres": list("GENRE"),
+            "series": self.field(&fields, "SERIES"),
+            "ser_no": self.field(&fields, "SERNO").parse::<i32>().ok(),
+            "file": self.field(&fields, "FILE"),
+            "file_size": self.field(&fields, "SIZE").parse::<i32>().unwrap_or(0),
+            "lib_id": self.field(&fields, "LIBID"),
+            "del": self.field(&fields, "DEL") == "1",
+            "ext": self.field(&fields, "EXT"),
+            "date": optional("DATE"),
+            "lang": self.field(&fields, "LANG"),
+            "keywords": keywords,
+            "rate": rate,
+            "insno": optional("INSNO"),
+            "container": optional("FOLDER").unwrap_or(container),
+        }))
+    }
+}
+
+// parse_author splits the INPX "Last,First,Middle" author into the name parts,
+// the display name is "First Middle Last" and the sort key is "last first middle"
+fn parse_author(author: &str) -> Option<Value> {
+    let mut parts = author.split(',').map(str::trim);
+    let last = parts.next().unwrap_or("");
+    let first = parts.next().unwrap_or("");
+    let middle = parts.next().unwrap_or("");
+
+    let join = |parts: [&str; 3]| {
+        parts
+            .iter()
+            .filter(|p| !p.is_empty())
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    };
+    let name = join([first, middle, last]);
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(json!({
+        "last": last,
+        "first": first,
+        "middle": middle,
+        "name": name,
+        "sort": join([last, first, middle]).to_lowercase(),
+    }))
+}
+
+// read_library_info returns the collection description from the collection.info
+// and the backup date from the version.info of the INPX archive
+pub fn read_library_info<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Value, Box<dyn Error>> {
+    let collection = read_info(archive, COLLECTION_INFO)?.unwrap_or_default();
+    let lines: Vec<&str> = collection.lines().map(str::trim).collect();
+    let line = |i: usize| lines.get(i).copied().unwrap_or("");
+
+    // the version is the backup date in the yyyymmdd format
+    let version = read_info(archive, VERSION_INFO)?.unwrap_or_default();
+    let version = chrono::NaiveDate::parse_from_str(version.trim(), "%Y%m%d")
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|_| version.trim().to_string());
+
+    Ok(json!({
+        "name": line(0),
+        "code": line(1),
+        "type": line(2).parse::<i64>().ok(),
+        "description": line(3),
+        "url": line(4),
+        "version": version,
+    }))
+}
+
+// read_info reads the text file of the INPX archive, the names are case insensitive
+fn read_info<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<String>, Box<dyn Error>> {
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.name().eq_ignore_ascii_case(name) {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            let text = String::from_utf8_lossy(&content);
+            return Ok(Some(text.trim_start_matches('\u{feff}').to_string()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(fields: &[&str]) -> String {
+        fields.join("\x04")
+    }
+
+    #[test]
+    fn process_book_default_structure() {
+        let structure = InpxStructure::new(DEFAULT_STRUCTURE).unwrap();
+        let l = line(&[
+            "Толстой,Лев,Николаевич:",
+            "prose_classic:",
+            "Война и мир",
+            "Война и мир",
+            "2",
+            "12345",
+            "1024",
+            "12345",
+            "1",
+            "fb2",
+            "2010-01-02",
+            "ru",
+            "5",
+            "роман, эпопея",
+            "",
+        ]);
+        let rec = structure.process_book(&l, "fb2-000001.zip").unwrap();
+
+        assert_eq!(rec["title"], "Война и мир");
+        assert_eq!(rec["authors"], json!(["Лев Николаевич Толстой"]));
+        assert_eq!(rec["author_sort"], "толстой лев николаевич");
+        assert_eq!(rec["genres"], json!(["prose_classic"]));
+        assert_eq!(rec["ser_no"], 2);
+        assert_eq!(rec["file_size"], 1024);
+        assert_eq!(rec["del"], true);
+        assert_eq!(rec["date"], "2010-01-02");
+        assert_eq!(rec["rate"], 5);
+        assert_eq!(rec["keywords"], json!(["роман", "эпопея"]));
+        assert_eq!(rec["container"], "fb2-000001.zip");
+    }
+
+    #[test]
+    fn process_book_skips_short_lines() {
+        let structure = InpxStructure::new(DEFAULT_STRUCTURE).unwrap();
+        // FILE is the 6th field
+        let l = line(&["Толстой,Лев,:", "prose_classic:", "Война и мир", "", "1"]);
+        assert!(structure.process_book(&l, "fb2-000001.zip").is_none());
+    }
+
+    #[test]
+    fn process_book_optional_fields_missing() {
+        let structure = InpxStructure::new(DEFAULT_STRUCTURE).unwrap();
+        let l = line(&["Толстой,Лев,:", "", "Детство", "", "", "777"]);
+        let rec = structure.process_book(&l, "fb2-000001.zip").unwrap();
+
+        assert_eq!(rec["title"], "Детство");
+        assert_eq!(rec["file"], "777");
+        assert_eq!(rec["ser_no"], Value::Null);
+        assert_eq!(rec["date"], Value::Null);
+        assert_eq!(rec["rate"], Value::Null);
+        assert_eq!(rec["del"], false);
+        assert_eq!(rec["genres"], json!([]));
+        assert_eq!(rec["keywords"], json!([]));
+    }
+
+    #[test]
+    fn process_book_reordered_structure() {
+        let structure = InpxStructure::new("file;title;ext;folder;author;rate;librate").unwrap();
+        let l = line(&[
+            "42",
+            "Анна Каренина",
+            "fb2",
+            "other.zip",
+            "Толстой,Лев,:",
+            "3",
+            "4",
+        ]);
+        let rec = structure.process_book(&l, "fb2-000001.zip").unwrap();
+
+        assert_eq!(rec["file"], "42");
+        assert_eq!(rec["title"], "Анна Каренина");
+        assert_eq!(rec["ext"], "fb2");
+        assert_eq!(rec["authors"], json!(["Лев Толстой"]));
+        // FOLDER overrides the container named after the .inp file
+        assert_eq!(rec["container"], "other.zip");
+        // LIBRATE is preferred to RATE
+        assert_eq!(rec["rate"], 4);
+    }
+
+    #[test]
+    fn structure_requires_fields() {
+        assert!(InpxStructure::new("AUTHOR;TITLE;SIZE;").is_err());
+        let structure = InpxStructure::new("TITLE;AUTHOR;FILE;").unwrap();
+        assert_eq!(structure.min_fields, 3);
+    }
+
+    #[test]
+    fn parse_author_full_name() {
+        let author = parse_author("Толстой,Лев,Николаевич").unwrap();
+        assert_eq!(author["last"], "Толстой");
+        assert_eq!(author["first"], "Лев");
+        assert_eq!(author["middle"], "Николаевич");
+        assert_eq!(author["name"], "Лев Николаевич Толстой");
+        assert_eq!(author["sort"], "толстой лев николаевич");
+    }
+
+    #[test]
+    fn parse_author_empty_parts() {
+        let author = parse_author("Толстой,Лев,").unwrap();
+        assert_eq!(author["middle"], "");
+        assert_eq!(author["name"], "Лев Толстой");
+        assert_eq!(author["sort"], "толстой лев");
+
+        let author = parse_author(" Гомер ,,").unwrap();
+        assert_eq!(author["name"], "Гомер");
+        assert_eq!(author["sort"], "гомер");
+
+        let author = parse_author(",Сомерсет,").unwrap();
+        assert_eq!(author["last"], "");
+        assert_eq!(author["name"], "Сомерсет");
+
+        assert!(parse_author(",,").is_none());
+        assert!(parse_author(" ").is_none());
+    }
+}
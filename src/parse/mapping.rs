@@ -0,0 +1,100 @@
+use serde_json::{json, Value};
+
+// text_field is the searchable text with the exact keyword used for sorting and
+// aggregations and the stemmed sub-fields for the language-aware matching
+fn text_field() -> Value {
+    json!({
+        "type": "text",
+        "fields": {
+            "keyword": {
+                "type": "keyword",
+                "ignore_above": 512
+            },
+            "ru": {
+                "type": "text",
+                "analyzer": "russian"
+            },
+            "en": {
+                "type": "text",
+                "analyzer": "english"
+            }
+        }
+    })
+}
+
+// lowercase_text_field is the text field with the lowercase keyword for the case insensitive
+// lookups and the alphabetical browsing
+fn lowercase_text_field() -> Value {
+    let mut field = text_field();
+    field["fields"]["lower"] = json!({
+        "type": "keyword",
+        "ignore_above": 512,
+        "normalizer": "lowercase"
+    });
+    field
+}
+
+// index_definition returns the settings and the mappings of the books index
+pub fn index_definition() -> Value {
+    json!({
+        "settings": {
+            "analysis": {
+                "normalizer": {
+                    "lowercase": {
+                        "type": "custom",
+                        "filter": ["lowercase"]
+                    }
+                }
+            }
+        },
+        "mappings": {
+            "dynamic": "strict",
+            "properties": {
+                "title": text_field(),
+                "authors": lowercase_text_field(),
+                "author_details": {
+                    "properties": {
+                        "last": {"type": "keyword"},
+                        "first": {"type": "keyword"},
+                        "middle": {"type": "keyword"},
+                        "name": {"type": "keyword"},
+                        "sort": {"type": "keyword"}
+                    }
+                },
+                "author_sort": {"type": "keyword"},
+                "series": lowercase_text_field(),
+                "ser_no": {"type": "integer"},
+                "genres": {"type": "keyword"},
+                "file": {"type": "keyword"},
+                "file_size": {"type": "integer"},
+                "lib_id": {"type": "keyword"},
+                "del": {"type": "boolean"},
+                "ext": {"type": "keyword"},
+                "date": {
+                    "type": "date",
+                    "format": "yyyy-MM-dd||strict_date_optional_time"
+                },
+                "lang": {"type": "keyword"},
+                "keywords": text_field(),
+                "annotation": {
+                    "type": "text",
+                    "fields": {
+                        "ru": {
+                            "type": "text",
+                            "analyzer": "russian"
+                        },
+                        "en": {
+                            "type": "text",
+                            "analyzer": "english"
+                        }
+                    }
+                },
+                "year": {"type": "integer"},
+                "rate": {"type": "integer"},
+                "insno": {"type": "keyword"},
+                "container": {"type": "keyword"},
+                "library": {"type": "keyword"}
+            }
+        }
+    })
+}
@@ -0,0 +1,586 @@
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+use zip::ZipArchive;
+
+use crate::conf;
+use crate::conf::Library;
+use crate::fb2;
+use crate::parse::bulk::{BulkQueue, InpxRecord};
+use crate::parse::indexer::Indexer;
+use crate::parse::inpx::InpxStructure;
+
+pub(crate) mod bulk;
+pub(crate) mod indexer;
+pub(crate) mod inpx;
+pub(crate) mod mapping;
+
+// DESCRIPTION_FIELDS are the fields indexed from the FB2 descriptions with --fb2
+const DESCRIPTION_FIELDS: [&str; 2] = ["annotation", "year"];
+
+// ParseOptions are the options of the parse command
+pub struct ParseOptions {
+    pub incremental: bool,
+    // append adds or overwrites the books of the INPX via the alias without removing
+    // anything, e.g. to import the rejected books again
+    pub append: bool,
+    // index is the versioned index the books are appended to instead of the alias one,
+    // the alias is switched to it afterwards, e.g. to finish the import left unswitched
+    pub index: Option<String>,
+    pub rejects_file: Option<String>,
+    pub allow_rejects: bool,
+    pub delete_old: bool,
+    // fb2 adds the annotations and the publication years from the books' descriptions
+    // to the index
+    pub fb2: bool,
+}
+
+// start imports the INPX of the given library or of all the configured libraries
+pub async fn start(
+    library: Option<&str>,
+    inpx: Option<&str>,
+    opts: &ParseOptions,
+) -> Result<(), Box<dyn Error>> {
+    let libraries = select_libraries(library)?;
+    if libraries.len() > 1
+        && (inpx.is_some() || opts.rejects_file.is_some() || opts.index.is_some())
+    {
+        return Err("Several libraries are configured, choose one with --library".into());
+    }
+
+    for library in libraries {
+        let file_name = match inpx {
+            Some(f) => f.to_string(),
+            None if !library.inpx.is_empty() => library.inpx.clone(),
+            None => {
+                return Err(format!("No INPX file given for the '{}' library", library.name).into())
+            }
+        };
+        import(&library, &file_name, opts).await?;
+    }
+    Ok(())
+}
+
+// select_libraries returns the library with the given name, or all the configured ones
+fn select_libraries(name: Option<&str>) -> Result<Vec<Library>, Box<dyn Error>> {
+    let libraries = conf::SETTINGS.read()?.libraries();
+    match name {
+        Some(name) => match libraries.into_iter().find(|l| l.name == name) {
+            Some(library) => Ok(vec![library]),
+            None => Err(format!("Unknown library '{}'", name).into()),
+        },
+        None => Ok(libraries),
+    }
+}
+
+async fn import(
+    library: &Library,
+    file_name: &str,
+    opts: &ParseOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut indexer = Indexer::new(library)?;
+
+    info!("Using the elasticsearch at '{}'", indexer.url);
+    info!(
+        "Parsing the '{}' file of the '{}' library",
+        file_name, library.name
+    );
+
+    let old_indices = indexer.alias_indices().await?;
+    if old_indices.is_empty() && indexer.index_exists(&indexer.alias).await? {
+        return Err(format!(
+            "'{}' is an index, not an alias: remove or rename it to import the books",
+            indexer.alias
+        )
+        .into());
+    }
+
+    let incremental = opts.incremental;
+    if let Some(index) = opts.index.as_ref() {
+        if !index.starts_with(&format!("{}-", indexer.alias))
+            || !indexer.index_exists(index).await?
+        {
+            return Err(format!(
+                "'{}' is not a version of the '{}' index",
+                index, indexer.alias
+            )
+            .into());
+        }
+        indexer.index = index.clone();
+    } else if (incremental || opts.append) && old_indices.is_empty() {
+        return Err(format!(
+            "The '{}' alias doesn't exist, run the full import first",
+            indexer.alias
+        )
+        .into());
+    }
+    if incremental {
+        info!(
+            "Incremental mode: diffing against the '{}' index",
+            indexer.alias
+        );
+    } else if opts.append {
+        info!(
+            "Append mode: adding the books to the '{}' index",
+            indexer.index
+        );
+    } else {
+        // the full import goes into a new index, the alias is switched to it on success
+        indexer.index = new_index_name(&indexer).await?;
+        indexer.create_index(&indexer.index).await?;
+        info!("Importing into the new '{}' index", indexer.index);
+    }
+
+    let indexer = Arc::new(indexer);
+    let mut queue = BulkQueue::new(indexer.clone())?;
+
+    let file = File::open(file_name)?;
+    let mut archive = ZipArchive::new(file)?;
+    let structure = InpxStructure::read(&mut archive)?;
+    let mut library_info = inpx::read_library_info(&mut archive)?;
+    info!(
+        "Collection '{}', backup version '{}'",
+        library_info["name"].as_str().unwrap_or_default(),
+        library_info["version"].as_str().unwrap_or_default()
+    );
+
+    let mut stats = ImportStats::default();
+    let mut containers = HashSet::new();
+    let mut books = ContainerReader::new(&library.path);
+    // books already indexed for the containers seen so far, all of them are removed
+    // from the index unless the new INPX still lists them. A container could be listed
+    // by several .inp files, so the removals are queued once all of them are read.
+    let mut existing: HashMap<String, HashMap<String, Value>> = HashMap::new();
+
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+
+        if file.name().ends_with(".inp") {
+            let inpx = file.name().to_string();
+            let inp_container = inpx.replace(".inp", ".zip");
+
+            let mut breader = BufReader::new(file);
+            let mut raw = Vec::new();
+            loop {
+                raw.clear();
+                if breader.read_until(b'\n', &mut raw)? == 0 {
+                    break;
+                }
+                let l = match String::from_utf8(std::mem::take(&mut raw)) {
+                    Ok(l) => l.trim_end_matches(['\r', '\n']).to_string(),
+                    Err(e) => {
+                        warn!("Skipping malformed line of '{}': {}", inp_container, e);
+                        continue;
+                    }
+                };
+                let mut rec = match structure.process_book(&l, &inp_container) {
+                    Some(rec) => rec,
+                    None => continue,
+                };
+                rec["library"] = json!(library.name);
+                let container = rec["container"].as_str().unwrap_or_default().to_string();
+                let first_seen = containers.insert(container.clone());
+
+                let id = book_id(&library.name, &container, &rec);
+                if incremental {
+                    if first_seen {
+                        let books = indexer.container_books(&container).await?;
+                        existing.insert(container.clone(), books);
+                    }
+                    let old = existing.get_mut(&container).and_then(|b| b.remove(&id));
+                    // INPX has no descriptions, the indexed ones are kept unless the book
+                    // is changed and its description is read again
+                    for field in DESCRIPTION_FIELDS {
+                        if let Some(value) = old.as_ref().and_then(|o| o.get(field)) {
+                            rec[field] = value.clone();
+                        }
+                    }
+                    match old {
+                        Some(old) if old == rec => {
+                            stats.unchanged += 1;
+                            continue;
+                        }
+                        Some(old) => {
+                            if old["del"] != rec["del"] && rec["del"] == true {
+                                stats.marked_deleted += 1;
+                            }
+                            stats.updated += 1;
+                        }
+                        None => stats.added += 1,
+                    }
+                } else {
+                    stats.added += 1;
+                }
+                if opts.fb2 && rec["ext"] == "fb2" {
+                    add_description(&mut books, &container, &mut rec);
+                }
+
+                let header = json!({
+                    "index": {
+                        "_index": indexer.index,
+                        "_id": id,
+                    }
+                });
+                let record = InpxRecord {
+                    inp: inpx.clone(),
+                    container,
+                    file: rec["file"].as_str().unwrap_or("").to_string(),
+                    line: l,
+                };
+                queue.push(&header, Some(&rec), Some(record)).await?;
+            }
+
+            info!("Queued the books of the '{}' file", inpx);
+        }
+    }
+
+    for id in existing.values().flat_map(|books| books.keys()) {
+        let header = json!({
+            "delete": {
+                "_index": indexer.index,
+                "_id": id,
+            }
+        });
+        queue.push(&header, None, None).await?;
+        stats.removed += 1;
+    }
+    let rejected = queue.finish().await?;
+    let rejected_count = rejected.len();
+    if rejected_count > 0 {
+        let rejects_file = opts
+            .rejects_file
+            .clone()
+            .unwrap_or_else(|| format!("rejects-{}.inpx", library.name));
+        error!(
+            "{} books were rejected, writing them to the '{}' file",
+            rejected_count, rejects_file
+        );
+        write_rejects(&rejects_file, &structure, rejected)?;
+    }
+
+    if opts.append {
+        // the appended INPX, e.g. the rejects file, doesn't describe the whole collection,
+        // so the library info is left as it is
+        indexer.refresh().await?;
+        info!("Import finished: {} books added or updated", stats.added);
+        if old_indices.contains(&indexer.index) || opts.index.is_none() {
+            return Ok(());
+        }
+        if rejected_count > 0 && !opts.allow_rejects {
+            return Err(format!(
+                "The import is incomplete, the '{}' alias is left unchanged, \
+                 append the rejects to the '{}' index again or use --allow-rejects",
+                indexer.alias, indexer.index
+            )
+            .into());
+        }
+        return switch_alias(&indexer, &old_indices, opts.delete_old).await;
+    }
+
+    library_info["imported_at"] = json!(chrono::Local::now().to_rfc3339());
+
+    if incremental {
+        // containers which disappeared from the backup completely
+        for container in indexer.indexed_containers().await? {
+            if !containers.contains(&container) {
+                let removed = indexer.delete_container(&container).await?;
+                info!("Removed {} books of the '{}' container", removed, container);
+                stats.removed += removed;
+            }
+        }
+
+        indexer.set_meta(&json!({"library": library_info})).await?;
+
+        info!(
+            "Import finished: {} added, {} updated ({} marked deleted), {} removed, {} unchanged",
+            stats.added, stats.updated, stats.marked_deleted, stats.removed, stats.unchanged
+        );
+    } else {
+        info!("Import finished: {} books indexed", stats.added);
+
+        // the library info is kept in the unswitched index too, the alias is switched
+        // to it once the rejects are appended
+        indexer.set_meta(&json!({"library": library_info})).await?;
+        indexer.refresh().await?;
+
+        if rejected_count > 0 && !opts.allow_rejects {
+            return Err(format!(
+                "The import is incomplete, the '{}' alias is left unchanged, the new index is '{}': \
+                 append the rejects with --append --index {} or use --allow-rejects",
+                indexer.alias, indexer.index, indexer.index
+            )
+            .into());
+        }
+
+        switch_alias(&indexer, &old_indices, opts.delete_old).await?;
+    }
+
+    Ok(())
+}
+
+// switch_alias points the alias to the imported index and deletes the previous versions
+// of the index if asked
+async fn switch_alias(
+    indexer: &Indexer,
+    old_indices: &[String],
+    delete_old: bool,
+) -> Result<(), Box<dyn Error>> {
+    indexer.switch_alias(old_indices).await?;
+    info!(
+        "The '{}' alias now points to the '{}' index",
+        indexer.alias, indexer.index
+    );
+
+    if delete_old {
+        for old in indexer.version_indices().await? {
+            if old != indexer.index {
+                indexer.delete_index(&old).await?;
+                info!("Deleted the old '{}' index", old);
+            }
+        }
+    }
+    Ok(())
+}
+
+// new_index_name returns the name of the versioned index for the new import
+async fn new_index_name(indexer: &Indexer) -> Result<String, Box<dyn Error>> {
+    let now = chrono::Local::now();
+    let name = format!("{}-{}", indexer.alias, now.format("%Y-%m-%d"));
+    if !indexer.index_exists(&name).await? {
+        return Ok(name);
+    }
+    Ok(format!(
+        "{}-{}",
+        indexer.alias,
+        now.format("%Y-%m-%d-%H%M%S")
+    ))
+}
+
+// init_index creates the empty versioned books indices with the explicit mapping
+// and points the aliases to them
+pub async fn init_index(library: Option<&str>) -> Result<(), Box<dyn Error>> {
+    for library in select_libraries(library)? {
+        create_library_index(&library).await?;
+    }
+    Ok(())
+}
+
+async fn create_library_index(library: &Library) -> Result<(), Box<dyn Error>> {
+    let mut indexer = Indexer::new(library)?;
+
+    info!("Using the elasticsearch at '{}'", indexer.url);
+    if indexer.index_exists(&indexer.alias).await? {
+        info!("The '{}' index already exists", indexer.alias);
+        return Ok(());
+    }
+
+    indexer.index = new_index_name(&indexer).await?;
+    indexer.create_index(&indexer.index).await?;
+    indexer.switch_alias(&[]).await?;
+    info!(
+        "Created the '{}' index aliased as '{}'",
+        indexer.index, indexer.alias
+    );
+    Ok(())
+}
+
+#[derive(Default)]
+struct ImportStats {
+    added: u64,
+    updated: u64,
+    marked_deleted: u64,
+    removed: u64,
+    unchanged: u64,
+}
+
+// write_rejects saves the original INPX lines of the rejected books as an INPX archive,
+// so they could be imported again with the parse command
+fn write_rejects(
+    file_name: &str,
+    structure: &InpxStructure,
+    rejected: Vec<InpxRecord>,
+) -> Result<(), Box<dyn Error>> {
+    let mut inps: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for record in rejected {
+        inps.entry(record.inp).or_default().push(record.line);
+    }
+
+    let file = File::create(file_name)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options: zip::write::FileOptions<()> =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file(inpx::STRUCTURE_INFO, options)?;
+    zip.write_all(structure.layout.as_bytes())?;
+
+    for (inp, lines) in inps {
+        zip.start_file(inp, options)?;
+        for line in lines {
+            zip.write_all(line.as_bytes())?;
+            zip.write_all(b"\r\n")?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+// add_description sets the annotation and the publication year of the book in its
+// document, the books which couldn't be read keep the ones they have
+fn add_description(books: &mut ContainerReader, container: &str, rec: &mut Value) {
+    let file = format!("{}.fb2", rec["file"].as_str().unwrap_or_default());
+    match books.description(container, &file) {
+        Ok(Some(desc)) => {
+            let year = desc.publication_year();
+            if let Some(rec) = rec.as_object_mut() {
+                for field in DESCRIPTION_FIELDS {
+                    rec.remove(field);
+                }
+                if !desc.annotation.is_empty() {
+                    rec.insert("annotation".to_string(), json!(desc.annotation));
+                }
+                if let Some(year) = year {
+                    rec.insert("year".to_string(), json!(year));
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(e) => warn!(
+            "Failed to read the description of {}/{}: {}",
+            container, file, e
+        ),
+    }
+}
+
+// ContainerReader reads the books from the containers of the library directory.
+// The INPX lists the books container by container, so the last one is kept open.
+struct ContainerReader {
+    root: PathBuf,
+    container: String,
+    archive: Option<ZipArchive<File>>,
+}
+
+impl ContainerReader {
+    fn new(root: &str) -> Self {
+        ContainerReader {
+            root: PathBuf::from(root),
+            container: String::new(),
+            archive: None,
+        }
+    }
+
+    // description parses the description of the book, None means the container is missing
+    fn description(
+        &mut self,
+        container: &str,
+        file: &str,
+    ) -> Result<Option<fb2::Description>, Box<dyn Error>> {
+        if self.container != container {
+            // the books of the corrupt container aren't looked up in the previous one
+            self.archive = None;
+            self.container = container.to_string();
+            let path = self.root.join(container);
+            self.archive = match File::open(&path) {
+                Ok(f) => Some(ZipArchive::new(f)?),
+                Err(e) => {
+                    // reported once per container
+                    warn!("Container {} is not available: {}", path.display(), e);
+                    None
+                }
+            };
+        }
+        let archive = match self.archive.as_mut() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        let book = archive.by_name(file)?;
+        Ok(Some(fb2::parse_description(BufReader::new(book))?))
+    }
+}
+
+// book_id derives the document id from the stable INPX data, so re-importing
+// a newer backup overwrites the existing documents instead of duplicating them.
+// The books of the default library keep the ids without the library prefix.
+fn book_id(library: &str, container: &str, rec: &Value) -> String {
+    let mut key = match rec["lib_id"].as_str() {
+        Some(lib_id) if !lib_id.is_empty() => format!("{}/{}", container, lib_id),
+        _ => format!("{}/{}", container, rec["file"].as_str().unwrap_or("")),
+    };
+    if library != conf::DEFAULT_LIBRARY {
+        key = format!("{}/{}", library, key);
+    }
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn book_id_stable() {
+        let container = "fb2-000001-000100.zip";
+        let rec = json!({"lib_id": "42", "file": "42"});
+        let id = book_id(conf::DEFAULT_LIBRARY, container, &rec);
+
+        assert_eq!(id, book_id(conf::DEFAULT_LIBRARY, container, &rec));
+        let key = format!("{}/42", container);
+        assert_eq!(
+            id,
+            Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes()).to_string()
+        );
+        // the other INPX fields don't change the id
+        let changed = json!({"lib_id": "42", "file": "42", "title": "Новое название"});
+        assert_eq!(id, book_id(conf::DEFAULT_LIBRARY, container, &changed));
+    }
+
+    #[test]
+    fn book_id_prefers_lib_id() {
+        let container = "fb2-000001-000100.zip";
+        let by_lib_id = book_id(
+            conf::DEFAULT_LIBRARY,
+            container,
+            &json!({"lib_id": "42", "file": "100"}),
+        );
+        let same_lib_id = book_id(
+            conf::DEFAULT_LIBRARY,
+            container,
+            &json!({"lib_id": "42", "file": "200"}),
+        );
+        assert_eq!(by_lib_id, same_lib_id);
+
+        // the file is the fallback of the missing or empty lib_id
+        let by_file = book_id(conf::DEFAULT_LIBRARY, container, &json!({"file": "100"}));
+        let empty_lib_id = book_id(
+            conf::DEFAULT_LIBRARY,
+            container,
+            &json!({"lib_id": "", "file": "100"}),
+        );
+        assert_eq!(by_file, empty_lib_id);
+        assert_ne!(by_file, by_lib_id);
+        let key = format!("{}/100", container);
+        assert_eq!(
+            by_file,
+            Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes()).to_string()
+        );
+    }
+
+    #[test]
+    fn book_id_library_prefix() {
+        let container = "fb2-000001-000100.zip";
+        let rec = json!({"lib_id": "42", "file": "42"});
+        let default = book_id(conf::DEFAULT_LIBRARY, container, &rec);
+        let other = book_id("librusec", container, &rec);
+
+        assert_ne!(default, other);
+        let key = format!("librusec/{}/42", container);
+        assert_eq!(
+            other,
+            Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes()).to_string()
+        );
+    }
+}
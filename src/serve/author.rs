@@ -0,0 +1,112 @@
+use axum::extract::{Json, Path, Query};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use serde_json::{json, Value};
+
+use crate::serve::request::LocalizedFilter;
+use crate::serve::{book_hit, genres, ES_CLIENT};
+
+// author_handler returns the author profile: the series with the books counts,
+// the books out of any series, the languages and the top genres
+pub async fn author_handler(
+    Path(name): Path<String>,
+    Query(params): Query<LocalizedFilter>,
+) -> impl IntoResponse {
+    let index = match ES_CLIENT.indices(&params.library) {
+        Ok(index) => index,
+        Err(e) => return (StatusCode::NOT_FOUND, Json(json!({"error": e}))),
+    };
+    match author_profile(&index, &name, &params.lang).await {
+        Ok(Some(profile)) => (StatusCode::OK, Json(profile)),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Author not found"})),
+        ),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))),
+    }
+}
+
+async fn author_profile(index: &str, name: &str, lang: &str) -> Result<Option<Value>, String> {
+    let author_filter = json!([
+        {"term": {"authors.keyword": name}},
+        {"term": {"del": false}}
+    ]);
+
+    // a single book is enough to get the structured name of the author
+    let query = json!({
+        "size": 1,
+        "_source": ["author_details"],
+        "track_total_hits": true,
+        "query": {"bool": {"filter": author_filter}},
+        "aggs": {
+            "series": {
+                "terms": {
+                    "field": "series.keyword",
+                    "exclude": [""],
+                    "size": 1000,
+                    "order": {"_key": "asc"}
+                }
+            },
+            "langs": {"terms": {"field": "lang", "size": 100}},
+            "genres": {"terms": {"field": "genres", "size": 10}}
+        }
+    });
+    let result = ES_CLIENT.search(index, query).await?;
+    let total = result["hits"]["total"]["value"].as_u64().unwrap_or(0);
+    if total == 0 {
+        return Ok(None);
+    }
+
+    let details = result["hits"]["hits"][0]["_source"]["author_details"]
+        .as_array()
+        .and_then(|authors| authors.iter().find(|a| a["name"] == name).cloned())
+        .unwrap_or(Value::Null);
+
+    let buckets = |agg: &str| -> Vec<Value> {
+        result["aggregations"][agg]["buckets"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+    };
+    let series: Vec<Value> = buckets("series")
+        .iter()
+        .map(|b| json!({"series": b["key"], "books": b["doc_count"]}))
+        .collect();
+    let langs: Vec<Value> = buckets("langs")
+        .iter()
+        .map(|b| json!({"value": b["key"], "count": b["doc_count"]}))
+        .collect();
+    let top_genres: Vec<Value> = buckets("genres")
+        .iter()
+        .map(|b| {
+            let code = b["key"].as_str().unwrap_or_default();
+            json!({"code": code, "name": genres::genre_name(code, lang), "count": b["doc_count"]})
+        })
+        .collect();
+
+    let mut filters = author_filter.as_array().cloned().unwrap_or_default();
+    filters.push(json!({"term": {"series.keyword": ""}}));
+    let query = json!({
+        "size": 1000,
+        "query": {"bool": {"filter": filters}},
+        "sort": ["title.keyword", "library", "container", "file"]
+    });
+    let result = ES_CLIENT.search(index, query).await?;
+    let standalone: Vec<Value> = result["hits"]["hits"]
+        .as_array()
+        .map(|hits| hits.iter().map(book_hit).collect())
+        .unwrap_or_default();
+
+    Ok(Some(json!({
+        "name": name,
+        "last": details["last"],
+        "first": details["first"],
+        "middle": details["middle"],
+        "sort": details["sort"],
+        "books": total,
+        "series": series,
+        "standalone": standalone,
+        "langs": langs,
+        "genres": top_genres,
+    })))
+}
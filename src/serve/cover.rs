@@ -0,0 +1,174 @@
+use axum::extract::{Json, Path, Query};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use image::{DynamicImage, ImageFormat};
+use log::error;
+use serde_json::json;
+use std::error::Error;
+use std::io::Cursor;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::conf;
+use crate::fb2;
+use crate::serve::request::CoverSize;
+use crate::serve::{get_book, get_book_file};
+
+// MAX_SIZE limits the thumbnails size, the larger covers are requested without the size
+const MAX_SIZE: u32 = 1000;
+const THUMBNAIL_TYPE: &str = "image/jpeg";
+
+// cover_handler returns the cover of the FB2 book, or its thumbnail fitting into the
+// size x size box. The thumbnails are cached on disk in the covers_cache directory.
+pub async fn cover_handler(
+    Path(book_id): Path<String>,
+    Query(params): Query<CoverSize>,
+) -> Response {
+    if params.size > MAX_SIZE {
+        let body = Json(json!({"error": format!("The size should not exceed {}", MAX_SIZE)}));
+        return (StatusCode::BAD_REQUEST, body).into_response();
+    }
+
+    let nfo = match get_book(&book_id).await {
+        Ok(n) => n,
+        Err(_) => {
+            let body = Json(json!({"error": "Book not found"}));
+            return (StatusCode::NOT_FOUND, body).into_response();
+        }
+    };
+    if nfo["ext"] != "fb2" {
+        let body = Json(json!({"error": "The book has no cover"}));
+        return (StatusCode::NOT_FOUND, body).into_response();
+    }
+
+    let cached = cache_file(&book_id, params.size);
+    if let Some(data) = cached.as_ref().and_then(|path| std::fs::read(path).ok()) {
+        return image_response(THUMBNAIL_TYPE, data);
+    }
+
+    let content = match get_book_file(&nfo).await {
+        Ok(content) => content,
+        Err(e) => {
+            let body = Json(json!({"error": e.to_string()}));
+            return (e.status(), body).into_response();
+        }
+    };
+    // the whole book is scanned for the cover, that's done off the async workers
+    let cover =
+        tokio::task::spawn_blocking(move || fb2::cover_image(&content).map_err(|e| e.to_string()))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|cover| cover);
+    let cover = match cover {
+        Ok(Some(cover)) => cover,
+        Ok(None) => {
+            let body = Json(json!({"error": "The book has no cover"}));
+            return (StatusCode::NOT_FOUND, body).into_response();
+        }
+        Err(e) => {
+            let body = Json(json!({"error": e}));
+            return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+        }
+    };
+
+    if params.size == 0 {
+        // the content type of the binary is optional, the image format is guessed then
+        let content_type = match cover.content_type.as_str() {
+            "" => image::guess_format(&cover.data)
+                .map(|f| f.to_mime_type())
+                .unwrap_or("application/octet-stream"),
+            content_type => content_type,
+        };
+        return image_response(content_type, cover.data);
+    }
+
+    let size = params.size;
+    let resized = tokio::task::spawn_blocking(move || {
+        thumbnail(&cover.data, size).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|data| data);
+    match resized {
+        Ok(data) => {
+            if let Some(path) = cached {
+                if let Err(e) = save_cache_file(&path, &data) {
+                    error!("Failed to cache the cover {}: {}", path.display(), e);
+                }
+            }
+            image_response(THUMBNAIL_TYPE, data)
+        }
+        Err(e) => {
+            let body = Json(json!({"error": e}));
+            (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+        }
+    }
+}
+
+fn image_response(content_type: &str, data: Vec<u8>) -> Response {
+    ([(CONTENT_TYPE, content_type.to_string())], data).into_response()
+}
+
+// thumbnail scales the image down to fit into the size x size box keeping its proportions,
+// the smaller images are only converted to JPEG
+fn thumbnail(data: &[u8], size: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut img = image::load_from_memory(data)?;
+    if img.width() > size || img.height() > size {
+        img = img.thumbnail(size, size);
+    }
+    // JPEG has no alpha channel
+    let img = DynamicImage::ImageRgb8(img.to_rgb8());
+
+    let mut out = Vec::new();
+    img.write_to(&mut Cursor::new(&mut out), ImageFormat::Jpeg)?;
+    Ok(out)
+}
+
+// cache_file returns the path of the cached thumbnail, the original covers aren't cached
+fn cache_file(book_id: &str, size: u32) -> Option<PathBuf> {
+    let dir = conf::SETTINGS.read().ok()?.covers_cache.clone();
+    // the book ids are UUIDs, anything else shouldn't get into the file name
+    let safe_id = book_id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if size == 0 || dir.is_empty() || !safe_id {
+        return None;
+    }
+    Some(PathBuf::from(dir).join(format!("{}-{}.jpg", book_id, size)))
+}
+
+// save_cache_file writes the thumbnail to the temporary file renamed into place,
+// so the concurrent requests never read the partially written one
+fn save_cache_file(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+    if let Err(e) = std::fs::write(&tmp, data) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_cache_file_replaces() {
+        let dir = std::env::temp_dir().join(format!("covers-{}", Uuid::new_v4()));
+        let path = dir.join("book-200.jpg");
+
+        save_cache_file(&path, b"first").unwrap();
+        save_cache_file(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        // no temporary files are left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
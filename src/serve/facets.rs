@@ -0,0 +1,111 @@
+use serde_json::{json, Map, Value};
+
+use crate::serve::genres;
+use crate::serve::request::Search;
+
+const FACET_SIZE: u32 = 50;
+const FACETS: &str = "genres, langs, authors, years, added_years";
+
+// aggregations returns the facets aggregations of the search request, years are
+// the publication years of the FB2 books, added_years are the years the books were
+// added to the library
+pub fn aggregations(facets: &[String]) -> Result<Value, String> {
+    let mut aggs = Map::new();
+    for facet in facets {
+        let agg = match facet.as_str() {
+            "genres" => json!({"terms": {"field": "genres", "size": FACET_SIZE}}),
+            "langs" => json!({"terms": {"field": "lang", "size": FACET_SIZE}}),
+            "authors" => json!({"terms": {"field": "authors.keyword", "size": FACET_SIZE}}),
+            "years" => json!({
+                "terms": {"field": "year", "size": FACET_SIZE, "order": {"_key": "desc"}}
+            }),
+            "added_years" => json!({
+                "date_histogram": {
+                    "field": "date",
+                    "calendar_interval": "year",
+                    "format": "yyyy",
+                    "min_doc_count": 1,
+                    "order": {"_key": "desc"}
+                }
+            }),
+            _ => {
+                return Err(format!(
+                    "Unknown facet: {}. Supported facets: {}",
+                    facet, FACETS
+                ))
+            }
+        };
+        aggs.insert(facet.clone(), agg);
+    }
+    Ok(Value::Object(aggs))
+}
+
+// filters returns the filters of the selected facets values
+pub fn filters(search: &Search) -> Vec<Value> {
+    let mut filters = Vec::new();
+
+    // the genres and the genre groups are alternatives of the same selection
+    let mut genres: Vec<Value> = search
+        .genre_groups
+        .iter()
+        .map(|g| genres::group_filter(g))
+        .collect();
+    if !search.genres.is_empty() {
+        genres.push(json!({"terms": {"genres": search.genres}}));
+    }
+    if !genres.is_empty() {
+        filters.push(json!({"bool": {"should": genres, "minimum_should_match": 1}}));
+    }
+    if !search.authors.is_empty() {
+        filters.push(json!({"terms": {"authors.keyword": search.authors}}));
+    }
+    if !search.years.is_empty() {
+        filters.push(json!({"terms": {"year": search.years}}));
+    }
+    if !search.added_years.is_empty() {
+        let years: Vec<Value> = search
+            .added_years
+            .iter()
+            .map(|year| {
+                json!({
+                    "range": {
+                        "date": {
+                            "gte": format!("{:04}-01-01", year),
+                            "lt": format!("{:04}-01-01", year + 1)
+                        }
+                    }
+                })
+            })
+            .collect();
+        filters.push(json!({"bool": {"should": years, "minimum_should_match": 1}}));
+    }
+
+    filters
+}
+
+// counts converts the facets aggregations of the response to {facet: [{value, count}]}
+pub fn counts(aggregations: &Value) -> Value {
+    let facets: Map<String, Value> = aggregations
+        .as_object()
+        .map(|aggs| {
+            aggs.iter()
+                .map(|(facet, agg)| {
+                    let buckets: Vec<Value> = agg["buckets"]
+                        .as_array()
+                        .map(|buckets| {
+                            buckets
+                                .iter()
+                                .map(|b| {
+                                    let value = b.get("key_as_string").unwrap_or(&b["key"]);
+                                    json!({"value": value, "count": b["doc_count"]})
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    (facet.clone(), json!(buckets))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Value::Object(facets)
+}
@@ -0,0 +1,378 @@
+// The FB2 genre codes used by the INPX backups, grouped the same way as in the
+// librusec/flibusta genre lists. The codes unknown to the table go to the "other" group.
+
+use axum::extract::{Json, Query};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::serve::request::LocalizedFilter;
+use crate::serve::ES_CLIENT;
+
+pub const OTHER_GROUP: &str = "other";
+
+// GROUPS are the genre groups: code, Russian and English names
+#[rustfmt::skip]
+const GROUPS: [(&str, &str, &str); 17] = [
+    ("sf", "Фантастика", "Science Fiction & Fantasy"),
+    ("detective", "Детективы и триллеры", "Detectives & Thrillers"),
+    ("prose", "Проза", "Prose"),
+    ("love", "Любовные романы", "Romance"),
+    ("adventure", "Приключения", "Adventure"),
+    ("children", "Детское", "Children's"),
+    ("poetry", "Поэзия и драматургия", "Poetry & Drama"),
+    ("antique", "Старинное", "Antique Literature"),
+    ("science", "Наука, образование", "Science & Education"),
+    ("computers", "Компьютеры и интернет", "Computers & Internet"),
+    ("reference", "Справочная литература", "Reference"),
+    ("nonfiction", "Документальная литература", "Nonfiction"),
+    ("religion", "Религия и духовность", "Religion & Spirituality"),
+    ("humor", "Юмор", "Humor"),
+    ("home", "Дом и семья", "Home & Family"),
+    ("business", "Экономика и бизнес", "Business"),
+    (OTHER_GROUP, "Прочее", "Other"),
+];
+
+// GENRES are the genres: code, group, Russian and English names
+#[rustfmt::skip]
+const GENRES: [(&str, &str, &str, &str); 169] = [
+    ("sf_history", "sf", "Альтернативная история", "Alternative History"),
+    ("sf_action", "sf", "Боевая фантастика", "Action Science Fiction"),
+    ("sf_epic", "sf", "Эпическая фантастика", "Epic Science Fiction"),
+    ("sf_heroic", "sf", "Героическая фантастика", "Heroic Fantasy"),
+    ("sf_detective", "sf", "Детективная фантастика", "Detective Science Fiction"),
+    ("sf_cyberpunk", "sf", "Киберпанк", "Cyberpunk"),
+    ("sf_space", "sf", "Космическая фантастика", "Space Science Fiction"),
+    ("sf_social", "sf", "Социально-психологическая фантастика", "Social Science Fiction"),
+    ("sf_horror", "sf", "Ужасы и мистика", "Horror & Mystic"),
+    ("sf_humor", "sf", "Юмористическая фантастика", "Humor Science Fiction"),
+    ("sf_fantasy", "sf", "Фэнтези", "Fantasy"),
+    ("sf_fantasy_city", "sf", "Городское фэнтези", "City Fantasy"),
+    ("sf_postapocalyptic", "sf", "Постапокалипсис", "Postapocalyptic"),
+    ("sf_etc", "sf", "Фантастика: прочее", "Science Fiction: Other"),
+    ("sf_mystic", "sf", "Мистика", "Mystic"),
+    ("sf_stimpank", "sf", "Стимпанк", "Steampunk"),
+    ("sf_technofantasy", "sf", "Технофэнтези", "Technofantasy"),
+    ("sf", "sf", "Научная фантастика", "Science Fiction"),
+    ("hronoopera", "sf", "Хроноопера", "Chrono Opera"),
+    ("popadanec", "sf", "Попаданцы", "Accidental Travelers"),
+    ("litrpg", "sf", "ЛитРПГ", "LitRPG"),
+    ("det_classic", "detective", "Классический детектив", "Classical Detective"),
+    ("det_police", "detective", "Полицейский детектив", "Police Stories"),
+    ("det_action", "detective", "Боевик", "Action"),
+    ("det_irony", "detective", "Иронический детектив", "Ironical Detective"),
+    ("det_history", "detective", "Исторический детектив", "Historical Detective"),
+    ("det_espionage", "detective", "Шпионский детектив", "Espionage Detective"),
+    ("det_crime", "detective", "Криминальный детектив", "Crime Detective"),
+    ("det_political", "detective", "Политический детектив", "Political Detective"),
+    ("det_maniac", "detective", "Маньяки", "Maniacs"),
+    ("det_hard", "detective", "Крутой детектив", "Hard-boiled Detective"),
+    ("det_cozy", "detective", "Дамский детектив", "Cozy Mystery"),
+    ("detective", "detective", "Детективы: прочее", "Detectives: Other"),
+    ("thriller", "detective", "Триллер", "Thriller"),
+    ("prose_classic", "prose", "Классическая проза", "Classics Prose"),
+    ("prose_history", "prose", "Историческая проза", "Historical Prose"),
+    ("prose_contemporary", "prose", "Современная проза", "Contemporary Prose"),
+    ("prose_counter", "prose", "Контркультура", "Counterculture"),
+    ("prose_rus_classic", "prose", "Русская классическая проза", "Russian Classics"),
+    ("prose_su_classics", "prose", "Советская классическая проза", "Soviet Classics"),
+    ("prose_military", "prose", "Проза о войне", "Military Prose"),
+    ("prose_magic", "prose", "Магический реализм", "Magic Realism"),
+    ("prose_abs", "prose", "Фантасмагория, абсурдистская проза", "Absurdist Prose"),
+    ("prose_neformatny", "prose", "Экспериментальная проза", "Experimental Prose"),
+    ("prose_epic", "prose", "Эпопея", "Epic"),
+    ("aphorism_quote", "prose", "Афоризмы, цитаты", "Aphorisms & Quotes"),
+    ("epistolary_fiction", "prose", "Эпистолярная проза", "Epistolary Fiction"),
+    ("prose", "prose", "Проза: прочее", "Prose: Other"),
+    ("love_contemporary", "love", "Современные любовные романы", "Contemporary Romance"),
+    ("love_history", "love", "Исторические любовные романы", "Historical Romance"),
+    ("love_detective", "love", "Остросюжетные любовные романы", "Detective Romance"),
+    ("love_short", "love", "Короткие любовные романы", "Short Romance"),
+    ("love_erotica", "love", "Эротика", "Erotica"),
+    ("love_sf", "love", "Любовное фэнтези, любовно-фантастические романы", "Romantic Fantasy"),
+    ("love_hard", "love", "Порно", "Hard Erotica"),
+    ("love", "love", "Любовные романы: прочее", "Romance: Other"),
+    ("adv_western", "adventure", "Вестерн", "Western"),
+    ("adv_history", "adventure", "Исторические приключения", "History Adventure"),
+    ("adv_indian", "adventure", "Приключения про индейцев", "Indians"),
+    ("adv_maritime", "adventure", "Морские приключения", "Maritime Fiction"),
+    ("adv_geo", "adventure", "Путешествия и география", "Travel & Geography"),
+    ("adv_animal", "adventure", "Природа и животные", "Nature & Animals"),
+    ("adventure", "adventure", "Приключения: прочее", "Adventure: Other"),
+    ("child_tale", "children", "Сказка", "Fairy Tales"),
+    ("child_verse", "children", "Детские стихи", "Verses for Children"),
+    ("child_prose", "children", "Детская проза", "Prose for Children"),
+    ("child_sf", "children", "Детская фантастика", "Science Fiction for Children"),
+    ("child_det", "children", "Детские остросюжетные", "Detectives & Thrillers for Children"),
+    ("child_adv", "children", "Детские приключения", "Adventures for Children"),
+    ("child_education", "children", "Детская образовательная литература", "Education for Children"),
+    ("child_folklore", "children", "Детский фольклор", "Folklore for Children"),
+    ("children", "children", "Детское: прочее", "Children's: Other"),
+    ("poetry", "poetry", "Поэзия", "Poetry"),
+    ("poetry_classical", "poetry", "Классическая поэзия", "Classical Poetry"),
+    ("poetry_modern", "poetry", "Современная поэзия", "Modern Poetry"),
+    ("poetry_for_classical", "poetry", "Классическая зарубежная поэзия", "Classical Foreign Poetry"),
+    ("poetry_rus_classical", "poetry", "Классическая русская поэзия", "Classical Russian Poetry"),
+    ("poetry_east", "poetry", "Поэзия Востока", "Eastern Poetry"),
+    ("song_poetry", "poetry", "Песенная поэзия", "Song Poetry"),
+    ("dramaturgy", "poetry", "Драматургия", "Dramaturgy"),
+    ("drama", "poetry", "Драма", "Drama"),
+    ("comedy", "poetry", "Комедия", "Comedy"),
+    ("tragedy", "poetry", "Трагедия", "Tragedy"),
+    ("antique_ant", "antique", "Античная литература", "Antique Literature"),
+    ("antique_european", "antique", "Европейская старинная литература", "European Antique Literature"),
+    ("antique_russian", "antique", "Древнерусская литература", "Old Russian Literature"),
+    ("antique_east", "antique", "Древневосточная литература", "Old Eastern Literature"),
+    ("antique_myths", "antique", "Мифы. Легенды. Эпос", "Myths, Legends & Epos"),
+    ("folklore", "antique", "Фольклор", "Folklore"),
+    ("folk_tale", "antique", "Народные сказки", "Folk Tales"),
+    ("antique", "antique", "Старинное: прочее", "Antique: Other"),
+    ("sci_history", "science", "История", "History"),
+    ("sci_psychology", "science", "Психология", "Psychology"),
+    ("sci_culture", "science", "Культурология", "Cultural Science"),
+    ("sci_religion", "science", "Религиоведение", "Religious Studies"),
+    ("sci_philosophy", "science", "Философия", "Philosophy"),
+    ("sci_politics", "science", "Политика", "Politics"),
+    ("sci_business", "science", "Деловая литература", "Business Literature"),
+    ("sci_juris", "science", "Юриспруденция", "Jurisprudence"),
+    ("sci_linguistic", "science", "Языкознание", "Linguistics"),
+    ("sci_medicine", "science", "Медицина", "Medicine"),
+    ("sci_phys", "science", "Физика", "Physics"),
+    ("sci_math", "science", "Математика", "Mathematics"),
+    ("sci_chem", "science", "Химия", "Chemistry"),
+    ("sci_biology", "science", "Биология", "Biology"),
+    ("sci_tech", "science", "Технические науки", "Technical Sciences"),
+    ("sci_cosmos", "science", "Астрономия и космос", "Astronomy & Space"),
+    ("sci_geo", "science", "Геология и география", "Geology & Geography"),
+    ("sci_ecology", "science", "Экология", "Ecology"),
+    ("sci_economy", "science", "Экономика", "Economy"),
+    ("sci_pedagogy", "science", "Педагогика", "Pedagogy"),
+    ("sci_social_studies", "science", "Обществознание", "Social Studies"),
+    ("science", "science", "Научная литература: прочее", "Science: Other"),
+    ("comp_www", "computers", "Интернет", "Internet"),
+    ("comp_programming", "computers", "Программирование", "Programming"),
+    ("comp_hard", "computers", "Компьютерное железо", "Hardware"),
+    ("comp_soft", "computers", "Программы", "Software"),
+    ("comp_db", "computers", "Базы данных", "Databases"),
+    ("comp_osnet", "computers", "ОС и сети", "OS & Networking"),
+    ("computers", "computers", "Компьютеры: прочее", "Computers: Other"),
+    ("ref_encyc", "reference", "Энциклопедии", "Encyclopedias"),
+    ("ref_dict", "reference", "Словари", "Dictionaries"),
+    ("ref_ref", "reference", "Справочники", "Reference"),
+    ("ref_guide", "reference", "Руководства", "Guidebooks"),
+    ("reference", "reference", "Справочная литература: прочее", "Reference: Other"),
+    ("nonf_biography", "nonfiction", "Биографии и мемуары", "Biography & Memoirs"),
+    ("nonf_publicism", "nonfiction", "Публицистика", "Publicism"),
+    ("nonf_criticism", "nonfiction", "Критика", "Criticism"),
+    ("nonf_military", "nonfiction", "Военная документалистика", "Military Nonfiction"),
+    ("design", "nonfiction", "Искусство и дизайн", "Art & Design"),
+    ("travel_notes", "nonfiction", "Путевые заметки", "Travel Notes"),
+    ("nonfiction", "nonfiction", "Документальная литература: прочее", "Nonfiction: Other"),
+    ("religion_rel", "religion", "Религия", "Religion"),
+    ("religion_esoterics", "religion", "Эзотерика", "Esoterics"),
+    ("religion_self", "religion", "Самосовершенствование", "Self-improvement"),
+    ("religion_orthodoxy", "religion", "Православие", "Orthodoxy"),
+    ("religion_catholicism", "religion", "Католицизм", "Catholicism"),
+    ("religion_protestantism", "religion", "Протестантизм", "Protestantism"),
+    ("religion_islam", "religion", "Ислам", "Islam"),
+    ("religion_judaism", "religion", "Иудаизм", "Judaism"),
+    ("religion_budda", "religion", "Буддизм", "Buddhism"),
+    ("religion", "religion", "Религия: прочее", "Religion: Other"),
+    ("humor_anecdote", "humor", "Анекдоты", "Anecdotes"),
+    ("humor_prose", "humor", "Юмористическая проза", "Humor Prose"),
+    ("humor_verse", "humor", "Юмористические стихи", "Humor Verses"),
+    ("humor_satire", "humor", "Сатира", "Satire"),
+    ("humor", "humor", "Юмор: прочее", "Humor: Other"),
+    ("home_cooking", "home", "Кулинария", "Cooking"),
+    ("home_pets", "home", "Домашние животные", "Pets"),
+    ("home_crafts", "home", "Хобби и ремесла", "Hobbies & Crafts"),
+    ("home_entertain", "home", "Развлечения", "Entertaining"),
+    ("home_health", "home", "Здоровье", "Health"),
+    ("home_garden", "home", "Сад и огород", "Garden"),
+    ("home_diy", "home", "Сделай сам", "Do It Yourself"),
+    ("home_sport", "home", "Спорт", "Sports"),
+    ("home_sex", "home", "Эротика, секс", "Erotica & Sex"),
+    ("home", "home", "Дом и семья: прочее", "Home: Other"),
+    ("banking", "business", "Банковское дело", "Banking"),
+    ("economics", "business", "Экономика", "Economics"),
+    ("marketing", "business", "Маркетинг, PR, реклама", "Marketing & PR"),
+    ("management", "business", "Управление, подбор персонала", "Management"),
+    ("popular_business", "business", "Карьера, кадры", "Career"),
+    ("small_business", "business", "Малый бизнес", "Small Business"),
+    ("real_estate", "business", "Недвижимость", "Real Estate"),
+    ("org_behavior", "business", "Корпоративная культура", "Corporate Culture"),
+    ("stock", "business", "Ценные бумаги, инвестиции", "Stock & Investments"),
+    ("periodic", OTHER_GROUP, "Журналы, газеты", "Periodicals"),
+    ("notes", OTHER_GROUP, "Партитуры", "Music Notes"),
+    ("other", OTHER_GROUP, "Неотсортированное", "Unsorted"),
+];
+
+// localized returns the localized name, English for the "en" language
+fn localized<'a>(ru: &'a str, en: &'a str, lang: &str) -> &'a str {
+    if lang == "en" {
+        en
+    } else {
+        ru
+    }
+}
+
+// genre_name returns the localized name of the genre, the unknown codes are kept as is
+pub fn genre_name(code: &str, lang: &str) -> String {
+    GENRES
+        .iter()
+        .find(|(c, _, _, _)| *c == code)
+        .map(|(_, _, ru, en)| localized(ru, en, lang))
+        .unwrap_or(code)
+        .to_string()
+}
+
+// groups returns the genre groups codes with the localized names
+pub fn groups(lang: &str) -> Vec<(&'static str, &'static str)> {
+    GROUPS
+        .iter()
+        .map(|(code, ru, en)| (*code, localized(ru, en, lang)))
+        .collect()
+}
+
+// group_name returns the localized name of the genre group
+pub fn group_name(group: &str, lang: &str) -> String {
+    GROUPS
+        .iter()
+        .find(|(code, _, _)| *code == group)
+        .map(|(_, ru, en)| localized(ru, en, lang))
+        .unwrap_or(group)
+        .to_string()
+}
+
+// group_codes returns the genre codes of the group
+fn group_codes(group: &str) -> Vec<&'static str> {
+    GENRES
+        .iter()
+        .filter(|(_, g, _, _)| *g == group)
+        .map(|(code, _, _, _)| *code)
+        .collect()
+}
+
+// group_filter returns the filter of the books of the genre group. The other group
+// also has the books with the genres unknown to the table only.
+pub fn group_filter(group: &str) -> Value {
+    let codes = group_codes(group);
+    if group != OTHER_GROUP {
+        return json!({"terms": {"genres": codes}});
+    }
+    let known: Vec<&str> = GENRES.iter().map(|(code, _, _, _)| *code).collect();
+    json!({
+        "bool": {
+            "should": [
+                {"terms": {"genres": codes}},
+                {
+                    "bool": {
+                        "filter": {"exists": {"field": "genres"}},
+                        "must_not": {"terms": {"genres": known}}
+                    }
+                }
+            ],
+            "minimum_should_match": 1
+        }
+    })
+}
+
+// group_genres returns the terms aggregation of the genres of the group, the other
+// group lists the unknown genres as well
+pub fn group_genres(group: &str) -> Value {
+    if group != OTHER_GROUP {
+        let codes = group_codes(group);
+        return json!({"field": "genres", "include": codes, "size": codes.len().max(1)});
+    }
+    let known: Vec<&str> = GENRES
+        .iter()
+        .filter(|(_, g, _, _)| *g != OTHER_GROUP)
+        .map(|(code, _, _, _)| *code)
+        .collect();
+    json!({"field": "genres", "exclude": known, "size": 1000})
+}
+
+// genres_handler returns the genres tree with the books counts,
+// the group counts are the numbers of the distinct books of the group
+pub async fn genres_handler(Query(params): Query<LocalizedFilter>) -> impl IntoResponse {
+    match genres_tree(&params).await {
+        Ok(body) => (StatusCode::OK, Json(body)),
+        Err(e) => (StatusCode::NOT_FOUND, Json(json!({"error": e}))),
+    }
+}
+
+async fn genres_tree(params: &LocalizedFilter) -> Result<Value, String> {
+    let indices = ES_CLIENT.indices(&params.library)?;
+
+    let query = json!({
+        "size": 0,
+        "query": {"term": {"del": false}},
+        "aggs": {
+            "genres": {"terms": {"field": "genres", "size": 1000}}
+        }
+    });
+    let result = ES_CLIENT.search(&indices, query).await?;
+    let counts: BTreeMap<String, u64> = result["aggregations"]["genres"]["buckets"]
+        .as_array()
+        .map(|buckets| {
+            buckets
+                .iter()
+                .filter_map(|b| Some((b["key"].as_str()?.to_string(), b["doc_count"].as_u64()?)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut codes: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (code, group, _, _) in GENRES.iter() {
+        codes.entry(*group).or_default().push(code.to_string());
+    }
+    for code in counts.keys() {
+        if !GENRES.iter().any(|(c, _, _, _)| c == code) {
+            codes.entry(OTHER_GROUP).or_default().push(code.clone());
+        }
+    }
+
+    let group_filters: Map<String, Value> = GROUPS
+        .iter()
+        .map(|(group, _, _)| (group.to_string(), group_filter(group)))
+        .collect();
+    let query = json!({
+        "size": 0,
+        "query": {"term": {"del": false}},
+        "aggs": {
+            "groups": {"filters": {"filters": group_filters}}
+        }
+    });
+    let result = ES_CLIENT.search(&indices, query).await?;
+    let group_counts = &result["aggregations"]["groups"]["buckets"];
+
+    let tree: Vec<Value> = GROUPS
+        .iter()
+        .map(|(group, ru, en)| {
+            let genres: Vec<Value> = codes
+                .get(group)
+                .map(|codes| {
+                    codes
+                        .iter()
+                        .map(|code| {
+                            json!({
+                                "code": code,
+                                "name": genre_name(code, &params.lang),
+                                "books": counts.get(code).copied().unwrap_or(0),
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            json!({
+                "code": group,
+                "name": localized(ru, en, &params.lang),
+                "books": group_counts[group]["doc_count"].as_u64().unwrap_or(0),
+                "genres": genres,
+            })
+        })
+        .collect();
+
+    Ok(json!(tree))
+}
@@ -0,0 +1,76 @@
+use axum::extract::{Json, Query};
+use axum::response::IntoResponse;
+use serde_json::{json, Map, Value};
+
+use crate::conf::Library;
+use crate::serve::request::LibraryFilter;
+use crate::serve::ES_CLIENT;
+
+// library_handler returns the metadata of the imported INPX collections
+// along with the books counts
+pub async fn library_handler(Query(filter): Query<LibraryFilter>) -> impl IntoResponse {
+    let libraries: Vec<&Library> = ES_CLIENT
+        .libraries
+        .iter()
+        .filter(|l| filter.library.is_empty() || l.name == filter.library)
+        .collect();
+
+    if libraries.is_empty() {
+        let body = json!({"error": format!("Unknown library: {}", filter.library)});
+        return (axum::http::StatusCode::NOT_FOUND, Json(body));
+    }
+
+    let mut body = Vec::new();
+    for library in libraries {
+        match library_info(library).await {
+            Ok(info) => body.push(info),
+            Err(e) => body.push(json!({"library": library.name, "error": e})),
+        }
+    }
+
+    (axum::http::StatusCode::OK, Json(json!(body)))
+}
+
+async fn library_info(library: &Library) -> Result<Value, String> {
+    let meta = ES_CLIENT.meta(&library.index).await?;
+
+    let query = json!({
+        "size": 0,
+        "track_total_hits": true,
+        "query": {
+            "term": {
+                "del": false
+            }
+        },
+        "aggs": {
+            "lang": {
+                "terms": {
+                    "field": "lang",
+                    "size": 1000
+                }
+            }
+        }
+    });
+    let result = ES_CLIENT.search(&library.index, query).await?;
+
+    let langs: Map<String, Value> = result["aggregations"]["lang"]["buckets"]
+        .as_array()
+        .map(|buckets| {
+            buckets
+                .iter()
+                .filter_map(|b| Some((b["key"].as_str()?.to_string(), b["doc_count"].clone())))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let info = &meta["library"];
+    Ok(json!({
+        "library": library.name,
+        "name": info["name"],
+        "description": info["description"],
+        "version": info["version"],
+        "imported_at": info["imported_at"],
+        "books": result["hits"]["total"]["value"],
+        "langs": langs,
+    }))
+}
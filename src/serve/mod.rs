@@ -1,8 +1,11 @@
 use axum::extract::{Json, Path, Query};
+use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::response::Response;
 use axum::routing::{get, post};
 use axum::Router;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use itertools::Itertools;
 use log::debug;
 use log::error;
@@ -16,10 +19,25 @@ use uuid::Uuid;
 use zip::ZipArchive;
 
 use crate::conf;
+use crate::conf::Library;
 use crate::convert::{get_format_content_type, FB2C_CONVERTER};
-use crate::serve::request::{ArchiveRequest, Author, DownloadFormat, Search};
-
+use crate::fb2;
+use crate::serve::request::{ArchiveRequest, Author, DownloadFormat, LibraryFilter, Search};
+
+pub(crate) mod author;
+pub(crate) mod cover;
+pub(crate) mod facets;
+pub(crate) mod genres;
+pub(crate) mod library;
+pub(crate) mod opds;
 pub(crate) mod request;
+pub(crate) mod series;
+pub(crate) mod translit;
+pub(crate) mod ui;
+
+// MAX_RESULT_WINDOW is the default index.max_result_window, the hits past it can't be read
+// with the offset
+const MAX_RESULT_WINDOW: u32 = 10000;
 
 lazy_static::lazy_static! {
     static ref ES_CLIENT: EsClient = EsClient::new().unwrap();
@@ -43,15 +61,30 @@ pub async fn start() -> Result<(), Box<dyn Error>> {
 
 fn build_router() -> axum::Router {
     Router::new()
+        .route("/", get(ui::index_handler))
+        .route("/ui/app.js", get(ui::app_js_handler))
+        .route("/ui/style.css", get(ui::style_css_handler))
         .route("/api/author/search", post(authors_handler))
         .route("/api/author/books", post(authors_books_handler))
+        .route("/api/author/{name}", get(author::author_handler))
         .route("/api/book/langs", get(langs_handler))
         .route("/api/book/search", post(title_search_handler))
+        .route("/api/book/find", post(find_handler))
         .route("/api/book/series", post(series_search_handler))
+        .route("/api/genre/books", post(genre_books_handler))
+        .route("/api/series/search", post(series::series_search_handler))
+        .route(
+            "/api/series/{name}/books",
+            get(series::series_books_handler),
+        )
         .route("/api/book/{id}", get(info_handler))
         .route("/api/book/{id}/download", get(download_handler))
+        .route("/api/book/{id}/cover", get(cover::cover_handler))
         .route("/api/book/archive", post(archive_handler))
         .route("/api/book/archive", get(archive_get_handler))
+        .route("/api/library", get(library::library_handler))
+        .route("/api/genres", get(genres::genres_handler))
+        .merge(opds::router())
 }
 
 struct EsClient {
@@ -59,6 +92,7 @@ struct EsClient {
     url: String,
     login: String,
     password: String,
+    libraries: Vec<Library>,
 }
 
 impl EsClient {
@@ -71,17 +105,38 @@ impl EsClient {
         let url = s.elastic_url.clone();
         let login = s.elastic_login.clone();
         let password = s.elastic_password.clone();
+        let libraries = s.libraries();
 
         Ok(EsClient {
             client: reqwest::Client::new(),
             url,
             login,
             password,
+            libraries,
         })
     }
 
+    // indices returns the index of the library, or all the libraries indices if it's empty
+    fn indices(&self, library: &str) -> Result<String, String> {
+        if library.is_empty() {
+            return Ok(self.libraries.iter().map(|l| l.index.as_str()).join(","));
+        }
+        self.library(library)
+            .map(|l| l.index.clone())
+            .ok_or_else(|| format!("Unknown library: {}", library))
+    }
+
+    fn library(&self, name: &str) -> Option<&Library> {
+        self.libraries.iter().find(|l| l.name == name)
+    }
+
+    // search runs the query on the index, the libraries not imported yet are skipped
+    // when searching across all the libraries
     async fn search(&self, index: &str, body: Value) -> Result<Value, String> {
-        let url = format!("{}/{}/_search", self.url, index);
+        let mut url = format!("{}/{}/_search", self.url, index);
+        if index.contains(',') {
+            url.push_str("?ignore_unavailable=true&allow_no_indices=true");
+        }
         debug!("ES search: url={}, body={}", url, body);
         let response = self
             .client
@@ -103,9 +158,10 @@ impl EsClient {
         }
     }
 
-    async fn get(&self, index: &str, id: &str) -> Result<Value, String> {
-        let url = format!("{}/{}/_doc/{}", self.url, index, id);
-        debug!("ES get: url={}", url);
+    // meta returns the import metadata stored in the mapping of the index
+    async fn meta(&self, index: &str) -> Result<Value, String> {
+        let url = format!("{}/{}/_mapping", self.url, index);
+        debug!("ES mapping: url={}", url);
         let response = self
             .client
             .get(&url)
@@ -114,29 +170,48 @@ impl EsClient {
             .await
             .map_err(|e| e.to_string())?;
 
-        if response.status().is_success() {
+        let status = response.status();
+        if status.is_success() {
             let body: Value = response.json().await.map_err(|e| e.to_string())?;
-            if let Some(source) = body.get("_source") {
-                let result = source.clone();
-                debug!("ES get success: id={}, response={}", id, result);
-                Ok(result)
-            } else {
-                Err("Document not found".to_string())
-            }
+            // the alias resolves to the single versioned index
+            let meta = body
+                .as_object()
+                .and_then(|indices| indices.values().next())
+                .map(|mapping| mapping["mappings"]["_meta"].clone())
+                .unwrap_or(Value::Null);
+            Ok(meta)
         } else {
-            Err("Document not found".to_string())
+            Err(format!("Mapping request failed: {}", status))
         }
     }
-}
 
-async fn es_search(query: Value, path: &str) -> Result<String, String> {
-    let index = {
-        let s = conf::SETTINGS.read();
-        match s {
-            Ok(s) => s.elastic_index.clone(),
-            Err(_) => return Err("Failed to read settings".to_string()),
+    // get looks up the book by id in the given comma separated indices
+    async fn get(&self, indices: &str, id: &str) -> Result<Value, String> {
+        let body = json!({
+            "size": 1,
+            "query": {
+                "ids": {
+                    "values": [id]
+                }
+            }
+        });
+        let result = self.search(indices, body).await?;
+
+        match result["hits"]["hits"]
+            .get(0)
+            .and_then(|hit| hit.get("_source"))
+        {
+            Some(source) => {
+                debug!("ES get success: id={}, response={}", id, source);
+                Ok(source.clone())
+            }
+            None => Err("Document not found".to_string()),
         }
-    };
+    }
+}
+
+async fn es_search(query: Value, path: &str, library: &str) -> Result<String, String> {
+    let index = ES_CLIENT.indices(library)?;
 
     debug!("ES query: index={}, path={}, query={}", index, path, query);
 
@@ -166,11 +241,7 @@ async fn es_search(query: Value, path: &str) -> Result<String, String> {
         let transformed = if path == "$.hits.hits" {
             data_to_serialize
                 .into_iter()
-                .map(|hit| {
-                    let id = hit.get("_id").cloned().unwrap_or(Value::Null);
-                    let book = hit.get("_source").cloned().unwrap_or(Value::Null);
-                    json!({"id": id, "book": book})
-                })
+                .map(|hit| book_hit(&hit))
                 .collect()
         } else {
             data_to_serialize
@@ -180,19 +251,57 @@ async fn es_search(query: Value, path: &str) -> Result<String, String> {
     }
 }
 
+// book_hit transforms the search hit to the {id, book} object
+fn book_hit(hit: &Value) -> Value {
+    let id = hit.get("_id").cloned().unwrap_or(Value::Null);
+    let book = hit.get("_source").cloned().unwrap_or(Value::Null);
+    // the relevance score is only present for the scored searches
+    match hit.get("_score").filter(|score| score.is_number()) {
+        Some(score) => json!({"id": id, "score": score, "book": book}),
+        None => json!({"id": id, "book": book}),
+    }
+}
+
 fn make_term<F>(query: &str, closure: F)
 where
     F: FnMut(String),
 {
     query
         .split_whitespace()
-        .map(|v| format!("*{}*", v.to_lowercase()))
+        .map(|v| v.to_lowercase())
         .for_each(closure);
 }
 
-fn compose_es_request(search: &Search, s_type: SearchType) -> Value {
-    let del = if search.deleted { 1 } else { 0 };
+// term_query matches the field containing the term, unless the fuzzy search is disabled
+// the term also matches with typos and in the other script via transliteration
+fn term_query(field: &str, term: &str, fuzzy: bool) -> Value {
+    let wildcard = |t: &str| json!({"wildcard": {field: format!("*{}*", t)}});
+    if !fuzzy {
+        return wildcard(term);
+    }
+
+    let mut should = Vec::new();
+    for variant in translit::variants(term) {
+        should.push(wildcard(&variant));
+        should.push(json!({
+            "match": {
+                field: {
+                    "query": variant,
+                    "fuzziness": "AUTO",
+                    "prefix_length": 1
+                }
+            }
+        }));
+    }
+    json!({
+        "bool": {
+            "should": should,
+            "minimum_should_match": 1
+        }
+    })
+}
 
+fn compose_es_request(search: &Search, s_type: SearchType) -> Value {
     let mut req = json!({
         "size": search.limit,
         "sort": [],
@@ -200,7 +309,7 @@ fn compose_es_request(search: &Search, s_type: SearchType) -> Value {
             "bool": {
                 "filter": [{
                     "terms": {
-                        "del": [0, del]
+                        "del": [false, search.deleted]
                     }
                 }]
             }
@@ -211,20 +320,20 @@ fn compose_es_request(search: &Search, s_type: SearchType) -> Value {
         SearchType::TitlesSearch => {
             let mut vec = Vec::new();
             make_term(&search.author, |term| {
-                vec.push(json!({"wildcard": {"authors": term}}))
+                vec.push(term_query("authors", &term, search.fuzzy))
             });
             make_term(&search.title, |term| {
-                vec.push(json!({"wildcard": {"title": term}}))
+                vec.push(term_query("title", &term, search.fuzzy))
             });
             vec
         }
         SearchType::SeriesSearch => {
             let mut vec = Vec::new();
             make_term(&search.author, |term| {
-                vec.push(json!({"wildcard": {"authors": term}}))
+                vec.push(term_query("authors", &term, search.fuzzy))
             });
             make_term(&search.series, |term| {
-                vec.push(json!({"wildcard": {"series": term}}))
+                vec.push(term_query("series", &term, search.fuzzy))
             });
             vec
         }
@@ -237,6 +346,54 @@ fn compose_es_request(search: &Search, s_type: SearchType) -> Value {
             }));
             vec
         }
+        SearchType::FreeSearch => {
+            // the free text query is scored, so it goes to `must` instead of the filters
+            let queries = if search.fuzzy {
+                translit::variants(&search.query.to_lowercase())
+            } else {
+                vec![search.query.clone()]
+            };
+            let should: Vec<Value> = queries
+                .iter()
+                .map(|query| {
+                    let mut multi_match = json!({
+                        "query": query,
+                        "type": "best_fields",
+                        "tie_breaker": 0.3,
+                        "fields": [
+                            "title^3",
+                            "title.ru^2",
+                            "title.en^2",
+                            "authors^2",
+                            "authors.ru",
+                            "series",
+                            "series.ru",
+                            "annotation^0.5",
+                            "annotation.ru^0.5"
+                        ]
+                    });
+                    if search.fuzzy {
+                        multi_match["fuzziness"] = json!("AUTO");
+                        multi_match["prefix_length"] = json!(1);
+                    }
+                    json!({"multi_match": multi_match})
+                })
+                .collect();
+            req["query"]["bool"]["must"] = json!({
+                "bool": {
+                    "should": should,
+                    "minimum_should_match": 1
+                }
+            });
+            Vec::new()
+        }
+        // the browsing is narrowed down by the facets filters only
+        SearchType::Browse => Vec::new(),
+        SearchType::SeriesBooks => vec![json!({
+            "term": {
+                "series.keyword": search.series
+            }
+        })],
     };
 
     if !search.langs.is_empty() {
@@ -246,6 +403,7 @@ fn compose_es_request(search: &Search, s_type: SearchType) -> Value {
             }
         }));
     }
+    filters.append(&mut facets::filters(search));
 
     req["query"].as_object_mut().unwrap()["bool"]
         .as_object_mut()
@@ -255,33 +413,46 @@ fn compose_es_request(search: &Search, s_type: SearchType) -> Value {
         .append(&mut filters);
 
     let mut sort = match s_type {
-        SearchType::SeriesSearch | SearchType::AuthorsBooks => {
+        SearchType::SeriesSearch | SearchType::SeriesBooks | SearchType::AuthorsBooks => {
             vec![
                 json!("series.keyword"),
-                json!("ser_no.keyword"),
+                json!("ser_no"),
                 json!("title.keyword"),
             ]
         }
         SearchType::TitlesSearch => vec![json!("title.keyword")],
+        SearchType::FreeSearch => vec![json!("_score"), json!("title.keyword")],
+        SearchType::Browse => match search.sort.as_str() {
+            "date" => vec![json!({"date": "desc"}), json!("title.keyword")],
+            "series" => vec![
+                json!("series.keyword"),
+                json!("ser_no"),
+                json!("title.keyword"),
+            ],
+            "author" => vec![json!("author_sort"), json!("title.keyword")],
+            _ => vec![json!("title.keyword")],
+        },
     };
+    // the unique tie-breaker keeps the order stable for the paging with search_after
+    sort.extend([json!("library"), json!("container"), json!("file")]);
 
     req["sort"].as_array_mut().unwrap().append(&mut sort);
 
     req
 }
 
-async fn langs_handler() -> impl IntoResponse {
+async fn langs_handler(Query(filter): Query<LibraryFilter>) -> impl IntoResponse {
     let query = json!({
         "size": 0,
         "query": {
-            "match": {
-                "del": "0"
+            "term": {
+                "del": false
             }
         },
         "aggs": {
             "lang": {
                 "terms": {
-                    "field": "lang.keyword",
+                    "field": "lang",
                     "include": ".*",
                     "size": 100
                 }
@@ -289,7 +460,7 @@ async fn langs_handler() -> impl IntoResponse {
         }
     });
 
-    match es_search(query, "$.aggregations.lang.buckets..key").await {
+    match es_search(query, "$.aggregations.lang.buckets..key", &filter.library).await {
         Ok(search_result) => match serde_json::from_str::<Value>(&search_result) {
             Ok(body) => (axum::http::StatusCode::OK, Json(body)),
             Err(_) => (
@@ -304,109 +475,244 @@ async fn langs_handler() -> impl IntoResponse {
     }
 }
 
+// authors_handler returns the authors whose names contain all the words of the query
+// regardless of the case, along with their books counts
 async fn authors_handler(Json(author_req): Json<Author>) -> impl IntoResponse {
-    let search_query = format!(
-        ".*{}.*",
-        author_req
-            .author
-            .split_whitespace()
-            .map(|v| {
-                let mut chars = v.chars();
-                match chars.next() {
-                    None => String::new(),
-                    Some(c) => c.to_uppercase().collect::<String>() + chars.as_str(),
-                }
-            })
-            .join(".*")
-    );
+    let mut terms = json!({
+        "field": "authors.lower",
+        "size": author_req.limit
+    });
+    if let Some(include) = authors_include(&author_req.author) {
+        terms["include"] = json!(include);
+    }
 
     let query = json!({
         "size": 0,
+        "query": {
+            "term": {
+                "del": false
+            }
+        },
         "aggs": {
             "author": {
-                "terms": {
-                    "field": "authors.keyword",
-                    "include": search_query,
-                    "size": author_req.limit
+                "terms": terms,
+                "aggs": {
+                    // the original case of the name, the co-authors have the lower counts
+                    "names": {
+                        "terms": {
+                            "field": "authors.keyword",
+                            "size": 10
+                        }
+                    }
                 }
             }
         }
     });
 
-    match es_search(query, "$.aggregations.author.buckets..key").await {
-        Ok(search_result) => match serde_json::from_str::<Value>(&search_result) {
-            Ok(body) => (axum::http::StatusCode::OK, Json(body)),
-            Err(_) => (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to parse response"})),
-            ),
-        },
-        Err(e) => (
-            axum::http::StatusCode::NOT_FOUND,
-            Json(json!([{"error": e.to_string()}])),
-        ),
+    let index = match ES_CLIENT.indices(&author_req.library) {
+        Ok(index) => index,
+        Err(e) => return (StatusCode::NOT_FOUND, Json(json!({"error": e}))),
+    };
+    let result = match ES_CLIENT.search(&index, query).await {
+        Ok(result) => result,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))),
+    };
+
+    let authors: Vec<Value> = result["aggregations"]["author"]["buckets"]
+        .as_array()
+        .map(|buckets| {
+            buckets
+                .iter()
+                .map(|b| {
+                    let key = b["key"].as_str().unwrap_or_default();
+                    let name = b["names"]["buckets"]
+                        .as_array()
+                        .and_then(|names| {
+                            names
+                                .iter()
+                                .filter_map(|n| n["key"].as_str())
+                                .find(|n| n.to_lowercase() == key)
+                        })
+                        .unwrap_or(key);
+                    json!({"name": name, "count": b["doc_count"]})
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if authors.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No matched data found"})),
+        );
     }
+    (StatusCode::OK, Json(json!(authors)))
 }
 
-async fn authors_books_handler(Json(search): Json<Search>) -> impl IntoResponse {
-    let req = compose_es_request(&search, SearchType::AuthorsBooks);
+// authors_include builds the regular expression of the authors terms matching all
+// the words of the query, None when there are no words
+fn authors_include(author: &str) -> Option<String> {
+    let mut words = Vec::new();
+    make_term(author, |term| {
+        words.push(format!("(.*{}.*)", escape_regex(&term)))
+    });
+    if words.is_empty() {
+        return None;
+    }
+    // & is the intersection of the regular expressions, so the words could go in any order
+    Some(words.join("&"))
+}
 
-    match es_search(req, "$.hits.hits").await {
-        Ok(search_result) => match serde_json::from_str(&search_result) {
-            Ok(body) => (axum::http::StatusCode::OK, Json(body)),
-            Err(_) => (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to parse response"})),
-            ),
-        },
-        Err(e) => (
-            axum::http::StatusCode::NOT_FOUND,
-            Json(json!({"error": e.to_string()})),
-        ),
+// escape_regex escapes the characters of the term, which are special
+// for the Lucene regular expressions
+fn escape_regex(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for c in term.chars() {
+        if !c.is_alphanumeric() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
     }
+    escaped
+}
+
+async fn authors_books_handler(Json(search): Json<Search>) -> impl IntoResponse {
+    search_books(&search, SearchType::AuthorsBooks).await
 }
 
 async fn title_search_handler(Json(search): Json<Search>) -> impl IntoResponse {
-    let req = compose_es_request(&search, SearchType::TitlesSearch);
+    search_books(&search, SearchType::TitlesSearch).await
+}
 
-    match es_search(req, "$.hits.hits").await {
-        Ok(search_result) => match serde_json::from_str(&search_result) {
-            Ok(body) => (axum::http::StatusCode::OK, Json(body)),
-            Err(_) => (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to parse response"})),
-            ),
-        },
-        Err(e) => (
-            axum::http::StatusCode::NOT_FOUND,
-            Json(json!({"error": e.to_string()})),
-        ),
+async fn series_search_handler(Json(search): Json<Search>) -> impl IntoResponse {
+    search_books(&search, SearchType::SeriesSearch).await
+}
+
+async fn find_handler(Json(search): Json<Search>) -> impl IntoResponse {
+    search_books(&search, SearchType::FreeSearch).await
+}
+
+// genre_books_handler lists the books of the genres or the genre groups
+async fn genre_books_handler(Json(search): Json<Search>) -> impl IntoResponse {
+    if search.genres.is_empty() && search.genre_groups.is_empty() {
+        let body = json!({"error": "Either genres or genre_groups is required"});
+        return (StatusCode::BAD_REQUEST, Json(body));
     }
+    if !["", "title", "date", "author", "series"].contains(&search.sort.as_str()) {
+        let body = json!({
+            "error": format!("Unknown sort: {}. Supported sorts: title, date, author, series", search.sort)
+        });
+        return (StatusCode::BAD_REQUEST, Json(body));
+    }
+    search_books(&search, SearchType::Browse).await
 }
 
-async fn series_search_handler(Json(search): Json<Search>) -> impl IntoResponse {
-    let req = compose_es_request(&search, SearchType::SeriesSearch);
+// search_books runs the books search, the hits are returned either as the bare array,
+// or wrapped into the page envelope with the total count and the next page cursor
+async fn search_books(search: &Search, s_type: SearchType) -> (StatusCode, Json<Value>) {
+    let mut req = compose_es_request(search, s_type);
+
+    if !search.paged() {
+        return match es_search(req, "$.hits.hits", &search.library).await {
+            Ok(search_result) => match serde_json::from_str(&search_result) {
+                Ok(body) => (StatusCode::OK, Json(body)),
+                Err(_) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to parse response"})),
+                ),
+            },
+            Err(e) => (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()}))),
+        };
+    }
 
-    match es_search(req, "$.hits.hits").await {
-        Ok(search_result) => match serde_json::from_str(&search_result) {
-            Ok(body) => (axum::http::StatusCode::OK, Json(body)),
-            Err(_) => (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to parse response"})),
-            ),
-        },
+    if let Err(e) = apply_paging(&mut req, search) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+    if !search.facets.is_empty() {
+        match facets::aggregations(&search.facets) {
+            Ok(aggs) => req["aggs"] = aggs,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+        }
+    }
+
+    match es_search_page(req, &search.library, search.limit).await {
+        Ok(page) => (StatusCode::OK, Json(page)),
         Err(e) => (
-            axum::http::StatusCode::NOT_FOUND,
+            StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": e.to_string()})),
         ),
     }
 }
 
+// apply_paging continues the search after the cursor, or skips the offset hits
+fn apply_paging(req: &mut Value, search: &Search) -> Result<(), String> {
+    req["track_total_hits"] = json!(true);
+    if !search.cursor.is_empty() {
+        let after = URL_SAFE_NO_PAD
+            .decode(&search.cursor)
+            .ok()
+            .and_then(|c| serde_json::from_slice::<Value>(&c).ok())
+            .filter(|c| c.is_array())
+            .ok_or("Invalid cursor")?;
+        req["search_after"] = after;
+    } else if search.offset > 0 {
+        req["from"] = json!(search.offset);
+    }
+    let from = if search.cursor.is_empty() {
+        search.offset
+    } else {
+        0
+    };
+    if from.saturating_add(search.limit) > MAX_RESULT_WINDOW {
+        return Err(format!(
+            "The offset and limit should not exceed {} hits, use the cursor for the deeper pages",
+            MAX_RESULT_WINDOW
+        ));
+    }
+    Ok(())
+}
+
+// es_search_page returns the page envelope with the facets counts, the next page cursor
+// encodes the sort values of the last hit
+async fn es_search_page(query: Value, library: &str, size: u32) -> Result<Value, String> {
+    let index = ES_CLIENT.indices(library)?;
+    let result = ES_CLIENT.search(&index, query).await?;
+
+    let hits = result["hits"]["hits"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    // there could be more hits only when the page is full
+    let next_cursor = match hits.last() {
+        Some(last) if hits.len() >= size as usize => {
+            let sort = serde_json::to_vec(&last["sort"]).map_err(|e| e.to_string())?;
+            json!(URL_SAFE_NO_PAD.encode(sort))
+        }
+        _ => Value::Null,
+    };
+
+    let mut page = json!({
+        "total": result["hits"]["total"]["value"],
+        "next_cursor": next_cursor,
+        "hits": hits.iter().map(book_hit).collect::<Vec<Value>>(),
+    });
+    if let Some(aggs) = result.get("aggregations") {
+        page["facets"] = facets::counts(aggs);
+    }
+    Ok(page)
+}
+
 async fn info_handler(Path(book_id): Path<String>) -> impl IntoResponse {
-    match ES_CLIENT.get("flibooks", &book_id).await {
+    match get_book(&book_id).await {
         Ok(nfo) => {
-            let body = Json(json!({"id": book_id, "book": nfo}));
-            (axum::http::StatusCode::OK, body).into_response()
+            let mut body = json!({"id": book_id, "book": nfo});
+            if nfo["ext"] == "fb2" {
+                match book_metadata(&book_id, &nfo).await {
+                    Ok(metadata) => body["metadata"] = metadata,
+                    Err(e) => error!("Failed to read the metadata of the book {}: {}", book_id, e),
+                }
+            }
+            (axum::http::StatusCode::OK, Json(body)).into_response()
         }
         Err(_) => {
             let body = Json(json!({"error": "Book not found"}));
@@ -415,11 +721,37 @@ async fn info_handler(Path(book_id): Path<String>) -> impl IntoResponse {
     }
 }
 
+// book_metadata reads the FB2 description of the book from its container,
+// the book is extracted and parsed off the async workers
+async fn book_metadata(book_id: &str, nfo: &Value) -> Result<Value, String> {
+    let nfo = nfo.clone();
+    let desc = tokio::task::spawn_blocking(move || {
+        let content = read_book_file(&nfo).map_err(|e| e.to_string())?;
+        fb2::parse_description(content.as_slice()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    let cover_url = if desc.cover.is_empty() {
+        Value::Null
+    } else {
+        json!(format!("/api/book/{}/cover", book_id))
+    };
+    Ok(json!({
+        "annotation": desc.annotation,
+        "keywords": desc.keywords,
+        "translators": desc.translators,
+        "publisher": desc.publisher,
+        "year": desc.year,
+        "isbn": desc.isbn,
+        "cover_url": cover_url,
+    }))
+}
+
 async fn download_handler(
     Path(book_id): Path<String>,
     Query(params): Query<DownloadFormat>,
 ) -> impl IntoResponse {
-    let nfo = match ES_CLIENT.get("flibooks", &book_id).await {
+    let nfo = match get_book(&book_id).await {
         Ok(n) => n,
         Err(_) => {
             let body = Json(json!({"error": "Book not found"}));
@@ -427,22 +759,16 @@ async fn download_handler(
         }
     };
 
-    let container = nfo["container"].as_str().unwrap();
-    let file_name = format!(
-        "{}.{}",
-        nfo["file"].as_str().unwrap(),
-        nfo["ext"].as_str().unwrap()
-    );
     let out_name = get_out_file_name(&nfo);
     let format = params.format;
 
     // Check if conversion is requested
-    if !format.is_empty() && format != "fb2"{
-        return download_converted_book(container, &file_name, &out_name, &format).await;
+    if !format.is_empty() && format != "fb2" {
+        return download_converted_book(&nfo, &out_name, &format).await;
     }
 
     // Download original file
-    match get_book_file(container, &file_name).await {
+    match get_book_file(&nfo).await {
         Ok(book_content) => {
             let mut response = Response::new(axum::body::Body::from(book_content));
             response.headers_mut().insert(
@@ -461,14 +787,13 @@ async fn download_handler(
         }
         Err(e) => {
             let body = Json(json!({"error": e.to_string()}));
-            (axum::http::StatusCode::NOT_FOUND, body).into_response()
+            (e.status(), body).into_response()
         }
     }
 }
 
 async fn download_converted_book(
-    container: &str,
-    file_name: &str,
+    nfo: &Value,
     out_name: &str,
     format: &str,
 ) -> Response<axum::body::Body> {
@@ -491,11 +816,11 @@ async fn download_converted_book(
     let src_path = temp_dir.path().join("file.fb2");
 
     // Extract FB2 file to temp location
-    let book_content = match get_book_file(container, file_name).await {
+    let book_content = match get_book_file(nfo).await {
         Ok(content) => content,
         Err(e) => {
             let body = Json(json!({"error": e.to_string()}));
-            return (axum::http::StatusCode::NOT_FOUND, body).into_response();
+            return (e.status(), body).into_response();
         }
     };
 
@@ -547,17 +872,6 @@ async fn download_converted_book(
 }
 
 async fn archive_handler(Json(archive_request): Json<ArchiveRequest>) -> impl IntoResponse {
-    let index = {
-        let s = conf::SETTINGS.read();
-        match s {
-            Ok(s) => s.elastic_index.clone(),
-            Err(_) => {
-                let body = Json(json!({"error": "Failed to read settings"}));
-                return (axum::http::StatusCode::BAD_REQUEST, body).into_response();
-            }
-        }
-    };
-
     let format = archive_request.format;
     let temp_dir = match tempfile::tempdir() {
         Ok(d) => d,
@@ -573,16 +887,10 @@ async fn archive_handler(Json(archive_request): Json<ArchiveRequest>) -> impl In
     }
 
     for id in &archive_request.id {
-        match ES_CLIENT.get(&index, id).await {
+        match get_book(id).await {
             Ok(nfo) => {
-                let container = nfo["container"].as_str().unwrap();
-                let file_name = format!(
-                    "{}.{}",
-                    nfo["file"].as_str().unwrap(),
-                    nfo["ext"].as_str().unwrap()
-                );
                 let out_name = get_out_file_name(&nfo);
-                match get_book_file(container, &file_name).await {
+                match get_book_file(&nfo).await {
                     Ok(book_content) => {
                         if format.is_empty() {
                             // Download original file without conversion
@@ -725,13 +1033,90 @@ async fn process_converted_book(
     }
 }
 
-async fn get_book_file(container: &str, file: &str) -> Result<Vec<u8>, String> {
-    let container_file = std::fs::File::open(container).map_err(|e| e.to_string())?;
-    let mut archive = ZipArchive::new(container_file).map_err(|e| e.to_string())?;
-    let mut book_content = archive.by_name(file).map_err(|e| e.to_string())?;
+// get_book looks up the book by id in all the libraries
+async fn get_book(id: &str) -> Result<Value, String> {
+    let indices = ES_CLIENT.indices("")?;
+    ES_CLIENT.get(&indices, id).await
+}
+
+// BookFileError tells the container missing from the library directory,
+// e.g. a partial mirror, from the book missing inside the existing container,
+// which means the index is out of sync with the archives
+enum BookFileError {
+    ContainerNotFound(String),
+    BookNotFound(String, String),
+    Failed(String),
+}
+
+impl BookFileError {
+    fn status(&self) -> axum::http::StatusCode {
+        match self {
+            BookFileError::ContainerNotFound(_) => axum::http::StatusCode::NOT_FOUND,
+            BookFileError::BookNotFound(_, _) | BookFileError::Failed(_) => {
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for BookFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookFileError::ContainerNotFound(container) => {
+                write!(f, "Container {} is not available", container)
+            }
+            BookFileError::BookNotFound(container, file) => {
+                write!(f, "Book {} is missing in the container {}", file, container)
+            }
+            BookFileError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// get_book_file extracts the book from its container, the container is looked up
+// in the directory of the book's library
+async fn get_book_file(nfo: &Value) -> Result<Vec<u8>, BookFileError> {
+    read_book_file(nfo)
+}
+
+// read_book_file is the blocking extraction of the book, for the code already running
+// off the async workers
+fn read_book_file(nfo: &Value) -> Result<Vec<u8>, BookFileError> {
+    let container = nfo["container"].as_str().unwrap_or_default();
+    let file = format!(
+        "{}.{}",
+        nfo["file"].as_str().unwrap_or_default(),
+        nfo["ext"].as_str().unwrap_or_default()
+    );
+    let library = nfo["library"].as_str().unwrap_or(conf::DEFAULT_LIBRARY);
+    let root = ES_CLIENT
+        .library(library)
+        .map(|l| l.path.as_str())
+        .unwrap_or("");
+    let path = std::path::Path::new(root).join(container);
+
+    let container_file = match std::fs::File::open(&path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            error!("Container {} not found", path.display());
+            return Err(BookFileError::ContainerNotFound(container.to_string()));
+        }
+        Err(e) => return Err(BookFileError::Failed(e.to_string())),
+    };
+    let mut archive =
+        ZipArchive::new(container_file).map_err(|e| BookFileError::Failed(e.to_string()))?;
+    let mut book_content = match archive.by_name(&file) {
+        Ok(b) => b,
+        Err(zip::result::ZipError::FileNotFound) => {
+            error!("Book {} not found in {}", file, path.display());
+            return Err(BookFileError::BookNotFound(container.to_string(), file));
+        }
+        Err(e) => return Err(BookFileError::Failed(e.to_string())),
+    };
 
     let mut buffer = Vec::new();
-    std::io::Read::read_to_end(&mut book_content, &mut buffer).map_err(|e| e.to_string())?;
+    std::io::Read::read_to_end(&mut book_content, &mut buffer)
+        .map_err(|e| BookFileError::Failed(e.to_string()))?;
     Ok(buffer)
 }
 
@@ -778,21 +1163,19 @@ fn truncate(s: &str, max_chars: usize) -> String {
 
 fn get_out_file_name(nfo: &Value) -> String {
     let title = nfo["title"].as_str().unwrap();
-    let trim_chars: &[char] = &[',', ' '];
 
     let auth_vec: Vec<&str> = nfo["authors"]
         .as_array()
         .unwrap()
         .iter()
-        .map(|a| a.as_str().unwrap().trim_matches(trim_chars))
+        .filter_map(|a| a.as_str())
         .collect();
 
     let mut authors = auth_vec.join(", ");
     authors = truncate(&authors, 100);
 
     let series = nfo["series"].as_str().unwrap_or("");
-    let ser = nfo["ser_no"].as_str().unwrap_or("");
-    if !series.is_empty() && !ser.is_empty() {
+    if let Some(ser) = nfo["ser_no"].as_i64().filter(|_| !series.is_empty()) {
         return format!("{} - [{}] {}.fb2", authors, ser, title);
     }
 
@@ -803,4 +1186,52 @@ enum SearchType {
     AuthorsBooks,
     TitlesSearch,
     SeriesSearch,
+    FreeSearch,
+    Browse,
+    SeriesBooks,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_regex_special_characters() {
+        assert_eq!(escape_regex("толстой"), "толстой");
+        assert_eq!(escape_regex("a(b"), "a\\(b");
+        assert_eq!(escape_regex("[x]"), "\\[x\\]");
+        assert_eq!(escape_regex("a\\b"), "a\\\\b");
+        assert_eq!(escape_regex("a.b"), "a\\.b");
+        assert_eq!(escape_regex("a&b"), "a\\&b");
+    }
+
+    #[test]
+    fn authors_include_words() {
+        assert_eq!(authors_include("  "), None);
+        assert_eq!(
+            authors_include("Лев Толстой"),
+            Some("(.*лев.*)&(.*толстой.*)".to_string())
+        );
+        // the special characters of the words don't break the intersection
+        assert_eq!(
+            authors_include("a&b (c."),
+            Some("(.*a\\&b.*)&(.*\\(c\\..*)".to_string())
+        );
+    }
+
+    #[test]
+    fn apply_paging_result_window() {
+        let search = |body: Value| Search::from_json(body).unwrap();
+        let mut req = json!({});
+
+        assert!(apply_paging(&mut req, &search(json!({"offset": 9950, "limit": 50}))).is_ok());
+        assert_eq!(req["from"], 9950);
+        assert!(apply_paging(&mut req, &search(json!({"offset": 9951, "limit": 50}))).is_err());
+        assert!(apply_paging(&mut req, &search(json!({"offset": u32::MAX, "limit": 50}))).is_err());
+
+        // the cursor pages aren't limited by the window
+        let cursor = URL_SAFE_NO_PAD.encode(b"[\"title\", 1]");
+        let body = json!({"offset": 20000, "limit": 50, "cursor": cursor});
+        assert!(apply_paging(&mut req, &search(body)).is_ok());
+    }
 }
@@ -0,0 +1,1030 @@
+// OPDS catalog for the e-reader apps: the feeds for browsing the books by authors,
+// series, genres and languages, the new arrivals and the OpenSearch. The same catalog
+// is served as the OPDS 1.2 Atom feeds and as the OPDS 2.0 JSON feeds.
+// The acquisition links point at the books download API.
+
+use axum::extract::{Path, Query, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde_json::{json, Value};
+
+use crate::convert::get_format_content_type;
+use crate::serve::request::{OpdsQuery, Search};
+use crate::serve::{
+    compose_es_request, escape_regex, facets, genres, SearchType, ES_CLIENT, MAX_RESULT_WINDOW,
+};
+
+const PAGE_SIZE: u32 = 50;
+// MAX_ITEMS is the number of the authors or series listed at once,
+// the larger lists are split into the groups by the next letter
+const MAX_ITEMS: usize = 100;
+const CONVERT_FORMATS: [&str; 3] = ["epub", "azw3", "mobi"];
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition/open-access";
+const IMAGE_REL: &str = "http://opds-spec.org/image";
+const THUMBNAIL_REL: &str = "http://opds-spec.org/image/thumbnail";
+const THUMBNAIL_SIZE: u32 = 200;
+const OPDS_JSON: &str = "application/opds+json";
+
+const NAVIGATION: &str = "navigation";
+const ACQUISITION: &str = "acquisition";
+
+// Format is the OPDS version of the catalog
+#[derive(Clone, Copy)]
+enum Format {
+    Atom,
+    Json,
+}
+
+impl Format {
+    fn base(&self) -> &'static str {
+        match self {
+            Format::Atom => "/opds",
+            Format::Json => "/opds/v2",
+        }
+    }
+}
+
+// Scope is the version of the catalog and the library it's limited to,
+// the catalog of all the libraries has the empty library
+#[derive(Clone)]
+struct Scope {
+    format: Format,
+    library: String,
+}
+
+impl Scope {
+    // base is the root of the catalog, the library catalogs are under /library/{name}
+    fn base(&self) -> String {
+        match self.library.as_str() {
+            "" => self.format.base().to_string(),
+            library => format!("{}/library/{}", self.format.base(), library),
+        }
+    }
+}
+
+// router serves the catalog of all the libraries and, when several libraries are configured,
+// the catalog of each one of them, both as the OPDS 1.2 and the OPDS 2.0 feeds
+pub fn router() -> Router {
+    let mut libraries = vec![String::new()];
+    if ES_CLIENT.libraries.len() > 1 {
+        libraries.extend(ES_CLIENT.libraries.iter().map(|l| l.name.clone()));
+    }
+
+    let mut router = Router::new();
+    for format in [Format::Atom, Format::Json] {
+        for library in &libraries {
+            let scope = Scope {
+                format,
+                library: library.clone(),
+            };
+            router = router.nest(&scope.base(), catalog_router(scope));
+        }
+    }
+    router
+}
+
+fn catalog_router(scope: Scope) -> Router {
+    Router::new()
+        .route("/", get(root_handler))
+        .route("/opensearch.xml", get(opensearch_handler))
+        .route("/search", get(search_handler))
+        .route("/new", get(new_handler))
+        .route("/authors", get(authors_handler))
+        .route("/author", get(author_books_handler))
+        .route("/series", get(series_handler))
+        .route("/series/books", get(series_books_handler))
+        .route("/genres", get(genre_groups_handler))
+        .route("/genres/{group}", get(genres_handler))
+        .route("/genre/{code}", get(genre_books_handler))
+        .route("/langs", get(langs_handler))
+        .route("/lang/{lang}", get(lang_books_handler))
+        .with_state(scope)
+}
+
+enum Entry {
+    // Navigation leads to the other feed
+    Navigation {
+        href: String,
+        title: String,
+        content: String,
+        count: Option<u64>,
+        kind: &'static str,
+    },
+    // Book is the search hit of the book
+    Book(Value),
+}
+
+// FacetLink narrows down the books feed to the facet value
+struct FacetLink {
+    group: &'static str,
+    title: String,
+    href: String,
+    count: u64,
+    active: bool,
+}
+
+// Feed is the OPDS feed rendered either as Atom or as JSON
+struct Feed {
+    scope: Scope,
+    href: String,
+    title: String,
+    kind: &'static str,
+    links: Vec<(&'static str, String)>,
+    total: Option<u64>,
+    page: u32,
+    entries: Vec<Entry>,
+    facets: Vec<FacetLink>,
+}
+
+impl Feed {
+    fn new(scope: &Scope, href: &str, title: &str, kind: &'static str) -> Self {
+        Feed {
+            scope: scope.clone(),
+            href: href.to_string(),
+            title: title.to_string(),
+            kind,
+            links: Vec::new(),
+            total: None,
+            page: 0,
+            entries: Vec::new(),
+            facets: Vec::new(),
+        }
+    }
+
+    fn navigation(&mut self, href: &str, title: &str, content: &str, kind: &'static str) {
+        self.entries.push(Entry::Navigation {
+            href: href.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            count: None,
+            kind,
+        });
+    }
+
+    // counted adds the navigation entry with the books count
+    fn counted(&mut self, href: &str, title: &str, bucket: &Value, kind: &'static str) {
+        let count = bucket["doc_count"].as_u64().unwrap_or(0);
+        self.entries.push(Entry::Navigation {
+            href: href.to_string(),
+            title: title.to_string(),
+            content: format!("{} books", count),
+            count: Some(count),
+            kind,
+        });
+    }
+
+    // paginate adds the links to the previous page and to the next one when the page is full
+    fn paginate(&mut self, page: u32, hits: usize) {
+        self.page = page;
+        if page > 0 {
+            let previous = with_params(&self.href, &[("page", &(page - 1).to_string())]);
+            self.links.push(("previous", previous));
+        }
+        let next_from = page.saturating_add(1).saturating_mul(PAGE_SIZE);
+        if hits >= PAGE_SIZE as usize && next_from < MAX_RESULT_WINDOW {
+            let next = with_params(&self.href, &[("page", &(page + 1).to_string())]);
+            self.links.push(("next", next));
+        }
+    }
+
+    fn render(&self) -> Response {
+        match self.scope.format {
+            Format::Atom => self.render_atom(),
+            Format::Json => self.render_json(),
+        }
+    }
+
+    fn render_atom(&self) -> Response {
+        let base = self.scope.base();
+        let kind = feed_type(self.kind);
+        let mut links = vec![
+            link("self", &self.href, &kind),
+            link("start", &base, &feed_type(NAVIGATION)),
+            link(
+                "search",
+                &format!("{}/opensearch.xml", base),
+                "application/opensearchdescription+xml",
+            ),
+        ];
+        for (rel, href) in &self.links {
+            links.push(link(rel, href, &kind));
+        }
+        for facet in &self.facets {
+            links.push(format!(
+                "<link rel=\"http://opds-spec.org/facet\" href=\"{}\" type=\"{}\" title=\"{}\" \
+                 opds:facetGroup=\"{}\" opds:activeFacet=\"{}\" thr:count=\"{}\"/>",
+                escape(&facet.href),
+                escape(&kind),
+                escape(&facet.title),
+                escape(facet.group),
+                facet.active,
+                facet.count
+            ));
+        }
+
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::Navigation {
+                    href,
+                    title,
+                    content,
+                    kind,
+                    ..
+                } => format!(
+                    "<entry><title>{}</title><id>{}</id><updated>{}</updated>{}\
+                     <content type=\"text\">{}</content></entry>",
+                    escape(title),
+                    escape(href),
+                    now(),
+                    link("subsection", href, &feed_type(kind)),
+                    escape(content)
+                ),
+                Entry::Book(hit) => book_entry(&base, hit),
+            })
+            .collect();
+
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" \
+             xmlns:opds=\"http://opds-spec.org/2010/catalog\" \
+             xmlns:thr=\"http://purl.org/syndication/thread/1.0\">\
+             <id>{}</id><title>{}</title><updated>{}</updated>{}{}</feed>",
+            escape(&self.href),
+            escape(&self.title),
+            now(),
+            links.concat(),
+            entries.concat()
+        );
+        ([(CONTENT_TYPE, kind)], body).into_response()
+    }
+
+    fn render_json(&self) -> Response {
+        let base = self.scope.base();
+        let mut links = vec![
+            json!({"rel": "self", "href": self.href, "type": OPDS_JSON}),
+            json!({"rel": "start", "href": base, "type": OPDS_JSON}),
+            json!({
+                "rel": "search",
+                "href": format!("{}/search{{?q}}", base),
+                "type": OPDS_JSON,
+                "templated": true
+            }),
+        ];
+        for (rel, href) in &self.links {
+            links.push(json!({"rel": rel, "href": href, "type": OPDS_JSON}));
+        }
+
+        let mut metadata = json!({"title": self.title});
+        if self.kind == ACQUISITION {
+            metadata["itemsPerPage"] = json!(PAGE_SIZE);
+            metadata["currentPage"] = json!(self.page + 1);
+            if let Some(total) = self.total {
+                metadata["numberOfItems"] = json!(total);
+            }
+        }
+        let mut body = json!({"metadata": metadata, "links": links});
+
+        let mut navigation = Vec::new();
+        let mut publications = Vec::new();
+        for entry in &self.entries {
+            match entry {
+                Entry::Navigation {
+                    href, title, count, ..
+                } => {
+                    let mut nav = json!({
+                        "href": href,
+                        "title": title,
+                        "type": OPDS_JSON,
+                        "rel": "subsection"
+                    });
+                    if let Some(count) = count {
+                        nav["properties"] = json!({"numberOfItems": count});
+                    }
+                    navigation.push(nav);
+                }
+                Entry::Book(hit) => publications.push(publication(&base, hit)),
+            }
+        }
+        if !navigation.is_empty() {
+            body["navigation"] = json!(navigation);
+        }
+        if self.kind == ACQUISITION {
+            body["publications"] = json!(publications);
+        }
+
+        // the facet links are grouped by the facets in the order of appearance
+        let mut groups: Vec<(&str, Vec<Value>)> = Vec::new();
+        for facet in &self.facets {
+            let mut facet_link = json!({
+                "href": facet.href,
+                "title": facet.title,
+                "type": OPDS_JSON,
+                "properties": {"numberOfItems": facet.count}
+            });
+            if facet.active {
+                facet_link["rel"] = json!("self");
+            }
+            match groups.iter_mut().find(|(group, _)| *group == facet.group) {
+                Some((_, facet_links)) => facet_links.push(facet_link),
+                None => groups.push((facet.group, vec![facet_link])),
+            }
+        }
+        if !groups.is_empty() {
+            body["facets"] = groups
+                .into_iter()
+                .map(|(group, links)| json!({"metadata": {"title": group}, "links": links}))
+                .collect();
+        }
+
+        ([(CONTENT_TYPE, OPDS_JSON)], body.to_string()).into_response()
+    }
+}
+
+fn feed_type(kind: &str) -> String {
+    format!("application/atom+xml;profile=opds-catalog;kind={}", kind)
+}
+
+fn link(rel: &str, href: &str, kind: &str) -> String {
+    format!(
+        "<link rel=\"{}\" href=\"{}\" type=\"{}\"/>",
+        escape(rel),
+        escape(href),
+        escape(kind)
+    )
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+// escape escapes the text for the XML content and attributes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+// with_params appends the non-empty query parameters to the link
+fn with_params(href: &str, params: &[(&str, &str)]) -> String {
+    let mut href = href.to_string();
+    for (name, value) in params.iter().filter(|(_, value)| !value.is_empty()) {
+        let sep = if href.contains('?') { '&' } else { '?' };
+        href = format!("{}{}{}={}", href, sep, name, encode(value));
+    }
+    href
+}
+
+fn error_response(status: StatusCode, e: String) -> Response {
+    (status, e).into_response()
+}
+
+async fn root_handler(State(scope): State<Scope>) -> Response {
+    let base = scope.base();
+    let title = match scope.library.as_str() {
+        "" => "Flibooks".to_string(),
+        library => format!("Flibooks: {}", library),
+    };
+    let mut feed = Feed::new(&scope, &base, &title, NAVIGATION);
+    let sections = [
+        (
+            "new",
+            "New arrivals",
+            "The recently added books",
+            ACQUISITION,
+        ),
+        ("authors", "Authors", "The books by authors", NAVIGATION),
+        ("series", "Series", "The books by series", NAVIGATION),
+        ("genres", "Genres", "The books by genres", NAVIGATION),
+        ("langs", "Languages", "The books by languages", NAVIGATION),
+    ];
+    for (path, title, content, kind) in sections {
+        feed.navigation(&format!("{}/{}", base, path), title, content, kind);
+    }
+    // the catalogs of the libraries when there are several of them
+    if scope.library.is_empty() && ES_CLIENT.libraries.len() > 1 {
+        for library in &ES_CLIENT.libraries {
+            let href = format!("{}/library/{}", base, library.name);
+            let content = format!("The books of the {} library", library.name);
+            feed.navigation(&href, &library.name, &content, NAVIGATION);
+        }
+    }
+    feed.render()
+}
+
+async fn opensearch_handler(State(scope): State<Scope>) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\">\
+         <ShortName>Flibooks</ShortName><Description>Search the books</Description>\
+         <InputEncoding>UTF-8</InputEncoding><OutputEncoding>UTF-8</OutputEncoding>\
+         <Url type=\"{}\" template=\"{}/search?q={{searchTerms}}\"/>\
+         </OpenSearchDescription>",
+        escape(&feed_type(ACQUISITION)),
+        scope.base()
+    );
+    (
+        [(CONTENT_TYPE, "application/opensearchdescription+xml")],
+        body,
+    )
+        .into_response()
+}
+
+// search_handler runs the free text search ranked by the relevance
+async fn search_handler(State(scope): State<Scope>, Query(params): Query<OpdsQuery>) -> Response {
+    let href = format!("{}/search?q={}", scope.base(), encode(&params.q));
+    let title = format!("Search: {}", params.q);
+    let search = json!({"query": params.q});
+    books_feed(
+        &scope,
+        &href,
+        &title,
+        search,
+        SearchType::FreeSearch,
+        &params,
+    )
+    .await
+}
+
+async fn new_handler(State(scope): State<Scope>, Query(params): Query<OpdsQuery>) -> Response {
+    let href = format!("{}/new", scope.base());
+    let search = json!({"sort": "date"});
+    books_feed(
+        &scope,
+        &href,
+        "New arrivals",
+        search,
+        SearchType::Browse,
+        &params,
+    )
+    .await
+}
+
+async fn author_books_handler(
+    State(scope): State<Scope>,
+    Query(params): Query<OpdsQuery>,
+) -> Response {
+    let href = format!("{}/author?name={}", scope.base(), encode(&params.name));
+    let search = json!({"authors": [params.name], "sort": "series"});
+    books_feed(
+        &scope,
+        &href,
+        &params.name,
+        search,
+        SearchType::Browse,
+        &params,
+    )
+    .await
+}
+
+async fn series_books_handler(
+    State(scope): State<Scope>,
+    Query(params): Query<OpdsQuery>,
+) -> Response {
+    let href = format!(
+        "{}/series/books?name={}",
+        scope.base(),
+        encode(&params.name)
+    );
+    let search = json!({"series": params.name});
+    books_feed(
+        &scope,
+        &href,
+        &params.name,
+        search,
+        SearchType::SeriesBooks,
+        &params,
+    )
+    .await
+}
+
+async fn genre_books_handler(
+    State(scope): State<Scope>,
+    Path(code): Path<String>,
+    Query(params): Query<OpdsQuery>,
+) -> Response {
+    let href = format!("{}/genre/{}", scope.base(), encode(&code));
+    let title = genres::genre_name(&code, "");
+    let search = json!({"genres": [code]});
+    books_feed(&scope, &href, &title, search, SearchType::Browse, &params).await
+}
+
+async fn lang_books_handler(
+    State(scope): State<Scope>,
+    Path(lang): Path<String>,
+    Query(params): Query<OpdsQuery>,
+) -> Response {
+    let href = format!("{}/lang/{}", scope.base(), encode(&lang));
+    let search = json!({"langs": [lang], "sort": "date"});
+    books_feed(&scope, &href, &lang, search, SearchType::Browse, &params).await
+}
+
+// books_feed runs the books search and renders the page of the acquisition feed
+// along with the language and genre facets, the selected facets narrow down the books
+async fn books_feed(
+    scope: &Scope,
+    href: &str,
+    title: &str,
+    search: Value,
+    s_type: SearchType,
+    params: &OpdsQuery,
+) -> Response {
+    let search = match Search::from_json(search) {
+        Ok(search) => search,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+    let from = match params.page.checked_mul(PAGE_SIZE) {
+        Some(from) if from < MAX_RESULT_WINDOW => from,
+        _ => {
+            let e = format!(
+                "The page should be less than {}",
+                MAX_RESULT_WINDOW / PAGE_SIZE
+            );
+            return error_response(StatusCode::BAD_REQUEST, e);
+        }
+    };
+    let mut query = compose_es_request(&search, s_type);
+    query["size"] = json!(PAGE_SIZE);
+    query["from"] = json!(from);
+    query["track_total_hits"] = json!(true);
+    let facet_names = vec!["langs".to_string(), "genres".to_string()];
+    query["aggs"] = match facets::aggregations(&facet_names) {
+        Ok(aggs) => aggs,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    let filters = query["query"]["bool"]["filter"].as_array_mut().unwrap();
+    if !params.lang.is_empty() {
+        filters.push(json!({"term": {"lang": params.lang}}));
+    }
+    if !params.genre.is_empty() {
+        filters.push(json!({"term": {"genres": params.genre}}));
+    }
+
+    let result = match run_query(&scope.library, query).await {
+        Ok(result) => result,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let hits = result["hits"]["hits"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    let feed_href = with_params(href, &[("lang", &params.lang), ("genre", &params.genre)]);
+    let mut feed = Feed::new(scope, &feed_href, title, ACQUISITION);
+    feed.total = result["hits"]["total"]["value"].as_u64();
+    feed.paginate(params.page, hits.len());
+
+    let counts = facets::counts(&result["aggregations"]);
+    for facet in counts["langs"].as_array().into_iter().flatten() {
+        let lang = facet["value"].as_str().unwrap_or_default();
+        feed.facets.push(FacetLink {
+            group: "Language",
+            title: lang.to_string(),
+            href: with_params(href, &[("lang", lang), ("genre", &params.genre)]),
+            count: facet["count"].as_u64().unwrap_or(0),
+            active: params.lang == lang,
+        });
+    }
+    for facet in counts["genres"].as_array().into_iter().flatten() {
+        let genre = facet["value"].as_str().unwrap_or_default();
+        feed.facets.push(FacetLink {
+            group: "Genre",
+            title: genres::genre_name(genre, ""),
+            href: with_params(href, &[("lang", &params.lang), ("genre", genre)]),
+            count: facet["count"].as_u64().unwrap_or(0),
+            active: params.genre == genre,
+        });
+    }
+
+    feed.entries = hits.into_iter().map(Entry::Book).collect();
+    feed.render()
+}
+
+async fn run_query(library: &str, query: Value) -> Result<Value, String> {
+    let index = ES_CLIENT.indices(library)?;
+    ES_CLIENT.search(&index, query).await
+}
+
+// acquisitions returns the links to the original file, the FB2 books could be
+// also converted to the other formats
+fn acquisitions(id: &str, ext: &str) -> Vec<(String, &'static str)> {
+    let download = format!("/api/book/{}/download", id);
+    let mut links = vec![(download.clone(), original_type(ext))];
+    if ext == "fb2" {
+        for format in CONVERT_FORMATS {
+            links.push((
+                format!("{}?format={}", download, format),
+                get_format_content_type(format).unwrap_or("application/octet-stream"),
+            ));
+        }
+    }
+    links
+}
+
+// covers returns the cover and the thumbnail links of the FB2 books with their content types,
+// the covers are extracted from the books on request, so the books without the cover get 404.
+// Only the thumbnails are always JPEG, the type of the original cover isn't known beforehand.
+fn covers(id: &str, ext: &str) -> Vec<(&'static str, String, Option<&'static str>)> {
+    if ext != "fb2" {
+        return Vec::new();
+    }
+    let cover = format!("/api/book/{}/cover", id);
+    vec![
+        (IMAGE_REL, cover.clone(), None),
+        (
+            THUMBNAIL_REL,
+            format!("{}?size={}", cover, THUMBNAIL_SIZE),
+            Some("image/jpeg"),
+        ),
+    ]
+}
+
+// original_type returns the content type of the book file as it's stored in the library
+fn original_type(ext: &str) -> &'static str {
+    match ext {
+        "fb2" => "application/fb2",
+        "epub" => "application/epub+zip",
+        "pdf" => "application/pdf",
+        "djvu" => "image/vnd.djvu",
+        "txt" => "text/plain",
+        "rtf" => "application/rtf",
+        "doc" => "application/msword",
+        _ => "application/octet-stream",
+    }
+}
+
+// updated returns the date the book was added to the library
+fn updated(book: &Value) -> String {
+    match book["date"].as_str() {
+        Some(date) if date.len() == 10 => format!("{}T00:00:00Z", date),
+        _ => now(),
+    }
+}
+
+fn str_values(value: &Value) -> Vec<&str> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect()
+}
+
+// book_entry renders the book as the Atom entry
+fn book_entry(base: &str, hit: &Value) -> String {
+    let id = hit["_id"].as_str().unwrap_or_default();
+    let book = &hit["_source"];
+
+    let mut entry = format!(
+        "<entry><title>{}</title><id>urn:uuid:{}</id><updated>{}</updated>",
+        escape(book["title"].as_str().unwrap_or_default()),
+        escape(id),
+        updated(book)
+    );
+    for name in str_values(&book["authors"]) {
+        entry.push_str(&format!(
+            "<author><name>{}</name><uri>{}</uri></author>",
+            escape(name),
+            escape(&format!("{}/author?name={}", base, encode(name)))
+        ));
+    }
+    for code in str_values(&book["genres"]) {
+        entry.push_str(&format!(
+            "<category term=\"{}\" label=\"{}\"/>",
+            escape(code),
+            escape(&genres::genre_name(code, ""))
+        ));
+    }
+    if let Some(lang) = book["lang"].as_str().filter(|l| !l.is_empty()) {
+        entry.push_str(&format!("<dc:language>{}</dc:language>", escape(lang)));
+    }
+
+    let series = book["series"].as_str().unwrap_or_default();
+    if !series.is_empty() {
+        let content = match book["ser_no"].as_i64() {
+            Some(n) => format!("{} [{}]", series, n),
+            None => series.to_string(),
+        };
+        entry.push_str(&format!(
+            "<content type=\"text\">{}</content>",
+            escape(&content)
+        ));
+    }
+
+    for (rel, href, kind) in covers(id, book["ext"].as_str().unwrap_or_default()) {
+        match kind {
+            Some(kind) => entry.push_str(&link(rel, &href, kind)),
+            None => entry.push_str(&format!(
+                "<link rel=\"{}\" href=\"{}\"/>",
+                escape(rel),
+                escape(&href)
+            )),
+        }
+    }
+    for (href, kind) in acquisitions(id, book["ext"].as_str().unwrap_or_default()) {
+        entry.push_str(&link(ACQUISITION_REL, &href, kind));
+    }
+
+    entry.push_str("</entry>");
+    entry
+}
+
+// publication renders the book as the OPDS 2.0 publication
+fn publication(base: &str, hit: &Value) -> Value {
+    let id = hit["_id"].as_str().unwrap_or_default();
+    let book = &hit["_source"];
+
+    let authors: Vec<Value> = str_values(&book["authors"])
+        .into_iter()
+        .map(|name| {
+            json!({
+                "name": name,
+                "links": [{
+                    "href": format!("{}/author?name={}", base, encode(name)),
+                    "type": OPDS_JSON
+                }]
+            })
+        })
+        .collect();
+    let subjects: Vec<Value> = str_values(&book["genres"])
+        .into_iter()
+        .map(|code| json!({"code": code, "name": genres::genre_name(code, "")}))
+        .collect();
+
+    let mut metadata = json!({
+        "@type": "http://schema.org/Book",
+        "identifier": format!("urn:uuid:{}", id),
+        "title": book["title"],
+        "author": authors,
+        "subject": subjects,
+        "modified": updated(book),
+    });
+    if let Some(lang) = book["lang"].as_str().filter(|l| !l.is_empty()) {
+        metadata["language"] = json!(lang);
+    }
+    if let Some(series) = book["series"].as_str().filter(|s| !s.is_empty()) {
+        let mut belongs = json!({"name": series});
+        if let Some(n) = book["ser_no"].as_i64() {
+            belongs["position"] = json!(n);
+        }
+        metadata["belongsTo"] = json!({"series": [belongs]});
+    }
+
+    let links: Vec<Value> = acquisitions(id, book["ext"].as_str().unwrap_or_default())
+        .into_iter()
+        .map(|(href, kind)| json!({"rel": ACQUISITION_REL, "href": href, "type": kind}))
+        .collect();
+
+    let mut body = json!({"metadata": metadata, "links": links});
+    let images: Vec<Value> = covers(id, book["ext"].as_str().unwrap_or_default())
+        .into_iter()
+        .map(|(_, href, kind)| match kind {
+            Some(kind) => json!({"href": href, "type": kind}),
+            None => json!({"href": href}),
+        })
+        .collect();
+    if !images.is_empty() {
+        body["images"] = json!(images);
+    }
+    body
+}
+
+async fn genre_groups_handler(State(scope): State<Scope>) -> Response {
+    let base = scope.base();
+    let mut feed = Feed::new(&scope, &format!("{}/genres", base), "Genres", NAVIGATION);
+    for (code, name) in genres::groups("") {
+        let href = format!("{}/genres/{}", base, encode(code));
+        feed.navigation(&href, name, name, NAVIGATION);
+    }
+    feed.render()
+}
+
+// genres_handler lists the genres of the group having any books
+async fn genres_handler(State(scope): State<Scope>, Path(group): Path<String>) -> Response {
+    let query = json!({
+        "size": 0,
+        "query": {"term": {"del": false}},
+        "aggs": {"genres": {"terms": genres::group_genres(&group)}}
+    });
+    let result = match run_query(&scope.library, query).await {
+        Ok(result) => result,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    let base = scope.base();
+    let href = format!("{}/genres/{}", base, encode(&group));
+    let title = genres::group_name(&group, "");
+    let mut feed = Feed::new(&scope, &href, &title, NAVIGATION);
+    for bucket in buckets(&result, "genres") {
+        let code = bucket["key"].as_str().unwrap_or_default();
+        let href = format!("{}/genre/{}", base, encode(code));
+        feed.counted(&href, &genres::genre_name(code, ""), &bucket, ACQUISITION);
+    }
+    feed.render()
+}
+
+async fn langs_handler(State(scope): State<Scope>) -> Response {
+    let query = json!({
+        "size": 0,
+        "query": {"term": {"del": false}},
+        "aggs": {"langs": {"terms": {"field": "lang", "size": 1000, "order": {"_key": "asc"}}}}
+    });
+    let result = match run_query(&scope.library, query).await {
+        Ok(result) => result,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    let base = scope.base();
+    let mut feed = Feed::new(&scope, &format!("{}/langs", base), "Languages", NAVIGATION);
+    for bucket in buckets(&result, "langs") {
+        let lang = bucket["key"].as_str().unwrap_or_default();
+        if !lang.is_empty() {
+            let href = format!("{}/lang/{}", base, encode(lang));
+            feed.counted(&href, lang, &bucket, ACQUISITION);
+        }
+    }
+    feed.render()
+}
+
+fn buckets(result: &Value, agg: &str) -> Vec<Value> {
+    result["aggregations"][agg]["buckets"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+}
+
+// Catalog is the alphabetical index of the authors or the series: the lowercase keys
+// to drill down by the letters and the original names to show
+struct Catalog {
+    path: &'static str,
+    title: &'static str,
+    books_path: &'static str,
+    keys: &'static str,
+    names: &'static str,
+}
+
+const AUTHORS: Catalog = Catalog {
+    path: "/authors",
+    title: "Authors",
+    books_path: "/author",
+    keys: "author_details.sort",
+    names: "author_details.name",
+};
+
+const SERIES: Catalog = Catalog {
+    path: "/series",
+    title: "Series",
+    books_path: "/series/books",
+    keys: "series.lower",
+    names: "series.keyword",
+};
+
+async fn authors_handler(State(scope): State<Scope>, Query(params): Query<OpdsQuery>) -> Response {
+    catalog_feed(&scope, &AUTHORS, &params.prefix.to_lowercase()).await
+}
+
+async fn series_handler(State(scope): State<Scope>, Query(params): Query<OpdsQuery>) -> Response {
+    catalog_feed(&scope, &SERIES, &params.prefix.to_lowercase()).await
+}
+
+// catalog_feed lists the authors or the series starting with the prefix, or the next
+// letter groups when there are too many of them
+async fn catalog_feed(scope: &Scope, catalog: &Catalog, prefix: &str) -> Response {
+    let mut filter = vec![json!({"term": {"del": false}})];
+    if !prefix.is_empty() {
+        filter.push(json!({"prefix": {catalog.keys: prefix}}));
+    }
+    let query = json!({
+        "size": 0,
+        "query": {"bool": {"filter": filter}},
+        "aggs": {
+            "items": {
+                "terms": {
+                    "field": catalog.keys,
+                    "include": format!("{}.*", escape_regex(prefix)),
+                    "size": MAX_ITEMS + 1,
+                    "order": {"_key": "asc"}
+                },
+                "aggs": {
+                    "names": {"terms": {"field": catalog.names, "size": 10}}
+                }
+            }
+        }
+    });
+    let result = match run_query(&scope.library, query.clone()).await {
+        Ok(result) => result,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    // the blank names, e.g. the bucket of the books without series, aren't browsable
+    let items: Vec<Value> = buckets(&result, "items")
+        .into_iter()
+        .filter(|item| item["key"].as_str().is_some_and(|k| !k.trim().is_empty()))
+        .collect();
+
+    let base = scope.base();
+    let path = format!("{}{}", base, catalog.path);
+    let href = with_params(&path, &[("prefix", prefix)]);
+    let title = match prefix {
+        "" => catalog.title.to_string(),
+        _ => format!("{}: {}", catalog.title, capitalize(prefix)),
+    };
+    let mut feed = Feed::new(scope, &href, &title, NAVIGATION);
+
+    let item_entry = |feed: &mut Feed, item: &Value| {
+        let name = item_name(item);
+        let href = format!("{}{}?name={}", base, catalog.books_path, encode(&name));
+        feed.counted(&href, &name, item, ACQUISITION);
+    };
+    if items.len() <= MAX_ITEMS {
+        for item in &items {
+            item_entry(&mut feed, item);
+        }
+        return feed.render();
+    }
+    // the item equal to the prefix is not in any of the longer groups
+    if let Some(item) = items.first().filter(|item| item["key"] == prefix) {
+        item_entry(&mut feed, item);
+    }
+
+    // the groups are the distinct prefixes one letter longer than the current one
+    let n = prefix.encode_utf16().count() + 1;
+    let mut query = query;
+    query["aggs"] = json!({
+        "groups": {
+            "terms": {
+                "script": {
+                    "source": "def groups = new ArrayList(); \
+                        for (def v : doc[params.field]) { \
+                            if (v.length() >= params.n && v.startsWith(params.prefix)) { \
+                                groups.add(v.substring(0, params.n)); \
+                            } \
+                        } \
+                        return groups;",
+                    "params": {"field": catalog.keys, "n": n, "prefix": prefix}
+                },
+                "size": 1000,
+                "order": {"_key": "asc"}
+            }
+        }
+    });
+    let result = match run_query(&scope.library, query).await {
+        Ok(result) => result,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    for group in buckets(&result, "groups") {
+        let key = group["key"].as_str().unwrap_or_default();
+        if key.trim().is_empty() {
+            continue;
+        }
+        let href = with_params(&path, &[("prefix", key)]);
+        feed.counted(&href, &capitalize(key), &group, NAVIGATION);
+    }
+    feed.render()
+}
+
+// item_name returns the original name of the catalog item, the lowercase key
+// has the same words, e.g. the "last first middle" sort key of the author
+fn item_name(item: &Value) -> String {
+    let key = item["key"].as_str().unwrap_or_default();
+    let words = |s: &str| {
+        let mut words: Vec<String> = s.split_whitespace().map(str::to_lowercase).collect();
+        words.sort();
+        words
+    };
+    item["names"]["buckets"]
+        .as_array()
+        .and_then(|names| {
+            names
+                .iter()
+                .filter_map(|n| n["key"].as_str())
+                .find(|n| words(n) == words(key))
+        })
+        .unwrap_or(key)
+        .to_string()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().collect::<String>() + chars.as_str(),
+        None => String::new(),
+    }
+}
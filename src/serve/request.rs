@@ -1,14 +1,20 @@
 use serde::Deserialize;
+use serde_json::Value;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Author {
     pub author: String,
     #[serde(default = "default_limit")]
     pub limit: u32,
+    #[serde(default = "default_empty_string")]
+    pub library: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Search {
+    // query is the free text matched across the title, authors and series
+    #[serde(default = "default_empty_string")]
+    pub query: String,
     #[serde(default = "default_empty_string")]
     pub title: String,
     #[serde(default = "default_empty_string")]
@@ -21,6 +27,88 @@ pub struct Search {
     pub deleted: bool,
     #[serde(default = "default_vec")]
     pub langs: Vec<String>,
+    #[serde(default = "default_empty_string")]
+    pub library: String,
+    // fuzzy enables the typos tolerant and transliterated matching of the search terms
+    #[serde(default = "default_fuzzy")]
+    pub fuzzy: bool,
+    // offset is the number of hits to skip, for the shallow paging
+    #[serde(default)]
+    pub offset: u32,
+    // cursor is the `next_cursor` of the previous page, for the deep paging
+    #[serde(default = "default_empty_string")]
+    pub cursor: String,
+    // envelope returns {total, next_cursor, hits} instead of the bare hits array,
+    // it's implied by the offset, cursor and facets
+    #[serde(default)]
+    pub envelope: bool,
+    // facets lists the facets to count: genres, langs, authors, years, added_years
+    #[serde(default = "default_vec")]
+    pub facets: Vec<String>,
+    // the selected facets values, the langs selection is the `langs` field
+    #[serde(default = "default_vec")]
+    pub genres: Vec<String>,
+    #[serde(default = "default_vec")]
+    pub authors: Vec<String>,
+    // years are the publication years, indexed for the FB2 books with parse --fb2
+    #[serde(default)]
+    pub years: Vec<i32>,
+    // added_years are the years the books were added to the library
+    #[serde(default)]
+    pub added_years: Vec<i32>,
+    // genre_groups selects the books of all the genres of the groups, e.g. "sf"
+    #[serde(default = "default_vec")]
+    pub genre_groups: Vec<String>,
+    // sort is the order of the browsed books: title (default), date, author or series
+    #[serde(default = "default_empty_string")]
+    pub sort: String,
+}
+
+impl Search {
+    // from_json builds the search of the given fields, the rest of them get
+    // the defaults of the request body
+    pub fn from_json(fields: Value) -> Result<Search, String> {
+        serde_json::from_value(fields).map_err(|e| e.to_string())
+    }
+
+    pub fn paged(&self) -> bool {
+        self.envelope || self.offset > 0 || !self.cursor.is_empty() || !self.facets.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryFilter {
+    #[serde(default = "default_empty_string")]
+    pub library: String,
+}
+
+// LocalizedFilter is the library filter of the responses with the genre names
+#[derive(Debug, Deserialize)]
+pub struct LocalizedFilter {
+    #[serde(default = "default_empty_string")]
+    pub library: String,
+    // lang is the language of the genre names: ru or en
+    #[serde(default = "default_empty_string")]
+    pub lang: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpdsQuery {
+    // q is the OpenSearch query
+    #[serde(default = "default_empty_string")]
+    pub q: String,
+    #[serde(default = "default_empty_string")]
+    pub name: String,
+    // prefix is the beginning of the authors or series names to browse
+    #[serde(default = "default_empty_string")]
+    pub prefix: String,
+    #[serde(default)]
+    pub page: u32,
+    // the selected language and genre facets of the books feeds
+    #[serde(default = "default_empty_string")]
+    pub lang: String,
+    #[serde(default = "default_empty_string")]
+    pub genre: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -29,6 +117,13 @@ pub struct DownloadFormat {
     pub format: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CoverSize {
+    // size is the maximum width and height of the thumbnail, the original cover by default
+    #[serde(default)]
+    pub size: u32,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ArchiveRequest {
     #[serde(default = "default_vec")]
@@ -41,6 +136,10 @@ pub fn default_limit() -> u32 {
     1000
 }
 
+pub fn default_fuzzy() -> bool {
+    true
+}
+
 pub fn default_deleted() -> bool {
     false
 }
@@ -0,0 +1,199 @@
+use axum::extract::{Json, Path, Query};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+use crate::serve::request::{LibraryFilter, Search};
+use crate::serve::{compose_es_request, search_books, SearchType, ES_CLIENT};
+
+// MAX_LISTED_NUMBER limits the numbering listing the missing numbers, the series
+// numbered by years or issues only tell whether the numbering has gaps
+const MAX_LISTED_NUMBER: i64 = 1000;
+
+// series_search_handler returns the series matching the search with their authors,
+// books counts and the numbering completeness
+pub async fn series_search_handler(Json(search): Json<Search>) -> impl IntoResponse {
+    let mut query = compose_es_request(&search, SearchType::SeriesSearch);
+    query["size"] = json!(0);
+    query.as_object_mut().unwrap().remove("sort");
+    // the books out of any series have the empty series
+    query["query"]["bool"]["must_not"] = json!({"term": {"series.keyword": ""}});
+    query["aggs"] = json!({
+        "series": {
+            "terms": {
+                "field": "series.keyword",
+                "size": search.limit,
+                "order": {"_key": "asc"}
+            },
+            "aggs": {
+                "authors": {"terms": {"field": "authors.keyword", "size": 10}},
+                "max_ser_no": {"max": {"field": "ser_no"}},
+                "numbered": {
+                    "filter": {"range": {"ser_no": {"gte": 1}}},
+                    "aggs": {
+                        "distinct": {
+                            "cardinality": {"field": "ser_no", "precision_threshold": 40000}
+                        },
+                        "ser_nos": {"terms": {"field": "ser_no", "size": MAX_LISTED_NUMBER}}
+                    }
+                }
+            }
+        }
+    });
+
+    let index = match ES_CLIENT.indices(&search.library) {
+        Ok(index) => index,
+        Err(e) => return (StatusCode::NOT_FOUND, Json(json!({"error": e}))),
+    };
+    let result = match ES_CLIENT.search(&index, query).await {
+        Ok(result) => result,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))),
+    };
+
+    let series: Vec<Value> = result["aggregations"]["series"]["buckets"]
+        .as_array()
+        .map(|buckets| buckets.iter().map(series_info).collect())
+        .unwrap_or_default();
+    (StatusCode::OK, Json(json!(series)))
+}
+
+// series_info converts the series bucket, the numbering has gaps when there are less
+// distinct numbers than the highest one. The missing numbers are listed for the numbering
+// up to MAX_LISTED_NUMBER only.
+fn series_info(bucket: &Value) -> Value {
+    let authors: Vec<Value> = bucket["authors"]["buckets"]
+        .as_array()
+        .map(|b| b.iter().map(|a| a["key"].clone()).collect())
+        .unwrap_or_default();
+    let max_ser_no = bucket["max_ser_no"]["value"].as_f64().map(|n| n as i64);
+    let distinct = bucket["numbered"]["distinct"]["value"]
+        .as_i64()
+        .unwrap_or(0);
+    let has_gaps = distinct < max_ser_no.unwrap_or(0);
+
+    let missing = match max_ser_no {
+        Some(max) if has_gaps && max <= MAX_LISTED_NUMBER => {
+            let numbers: HashSet<i64> = bucket["numbered"]["ser_nos"]["buckets"]
+                .as_array()
+                .map(|b| b.iter().filter_map(|n| n["key"].as_i64()).collect())
+                .unwrap_or_default();
+            json!((1..=max)
+                .filter(|n| !numbers.contains(n))
+                .collect::<Vec<i64>>())
+        }
+        // too long numbering to list the missing numbers
+        _ if has_gaps => Value::Null,
+        _ => json!([]),
+    };
+
+    json!({
+        "series": bucket["key"],
+        "authors": authors,
+        "books": bucket["doc_count"],
+        "max_ser_no": max_ser_no,
+        "has_gaps": has_gaps,
+        "missing": missing,
+    })
+}
+
+// series_books_handler returns the books of the series ordered by the numbers
+pub async fn series_books_handler(
+    Path(name): Path<String>,
+    Query(filter): Query<LibraryFilter>,
+) -> impl IntoResponse {
+    let search = match Search::from_json(json!({"series": name, "library": filter.library})) {
+        Ok(search) => search,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+    search_books(&search, SearchType::SeriesBooks).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // bucket builds the series aggregation bucket of the books with the given numbers
+    fn bucket(ser_nos: &[i64]) -> Value {
+        let mut counts: Vec<(i64, usize)> = Vec::new();
+        for n in ser_nos.iter().filter(|n| **n >= 1) {
+            match counts.iter_mut().find(|(key, _)| key == n) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((*n, 1)),
+            }
+        }
+        let max = ser_nos.iter().max().map(|n| *n as f64);
+        json!({
+            "key": "Война и мир",
+            "doc_count": ser_nos.len(),
+            "authors": {"buckets": [{"key": "Лев Толстой", "doc_count": ser_nos.len()}]},
+            "max_ser_no": {"value": max},
+            "numbered": {
+                "doc_count": counts.iter().map(|(_, c)| c).sum::<usize>(),
+                "distinct": {"value": counts.len()},
+                "ser_nos": {
+                    "buckets": counts
+                        .iter()
+                        .map(|(key, count)| json!({"key": key, "doc_count": count}))
+                        .collect::<Vec<Value>>()
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn series_info_complete_numbering() {
+        let info = series_info(&bucket(&[1, 2, 3]));
+        assert_eq!(info["series"], "Война и мир");
+        assert_eq!(info["authors"], json!(["Лев Толстой"]));
+        assert_eq!(info["books"], 3);
+        assert_eq!(info["max_ser_no"], 3);
+        assert_eq!(info["has_gaps"], false);
+        assert_eq!(info["missing"], json!([]));
+    }
+
+    #[test]
+    fn series_info_gap() {
+        let info = series_info(&bucket(&[1, 2, 5]));
+        assert_eq!(info["has_gaps"], true);
+        assert_eq!(info["missing"], json!([3, 4]));
+    }
+
+    #[test]
+    fn series_info_duplicated_number() {
+        // the books count matches the highest number, but the 3rd book is missing
+        let info = series_info(&bucket(&[1, 2, 2, 4]));
+        assert_eq!(info["books"], 4);
+        assert_eq!(info["has_gaps"], true);
+        assert_eq!(info["missing"], json!([3]));
+
+        let info = series_info(&bucket(&[1, 1, 2]));
+        assert_eq!(info["has_gaps"], false);
+        assert_eq!(info["missing"], json!([]));
+    }
+
+    #[test]
+    fn series_info_no_numbers() {
+        let info = series_info(&bucket(&[0, 0]));
+        assert_eq!(info["max_ser_no"], 0);
+        assert_eq!(info["has_gaps"], false);
+        assert_eq!(info["missing"], json!([]));
+
+        let info = series_info(&bucket(&[]));
+        assert_eq!(info["max_ser_no"], Value::Null);
+        assert_eq!(info["has_gaps"], false);
+        assert_eq!(info["missing"], json!([]));
+    }
+
+    #[test]
+    fn series_info_long_numbering() {
+        let info = series_info(&bucket(&[1, 2, MAX_LISTED_NUMBER + 1]));
+        assert_eq!(info["has_gaps"], true);
+        assert_eq!(info["missing"], Value::Null);
+
+        let numbers: Vec<i64> = (1..=MAX_LISTED_NUMBER + 1).collect();
+        let info = series_info(&bucket(&numbers));
+        assert_eq!(info["has_gaps"], false);
+        assert_eq!(info["missing"], json!([]));
+    }
+}
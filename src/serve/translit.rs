@@ -0,0 +1,198 @@
+// Latin <-> Cyrillic transliteration of the search terms, so the queries typed in
+// the "wrong" script still match e.g. "Dostoevsky" -> "достоевский".
+// The conversion is approximate, the fuzzy matching takes care of the rest.
+
+const CYRILLIC: [(char, &str); 33] = [
+    ('а', "a"),
+    ('б', "b"),
+    ('в', "v"),
+    ('г', "g"),
+    ('д', "d"),
+    ('е', "e"),
+    ('ё', "yo"),
+    ('ж', "zh"),
+    ('з', "z"),
+    ('и', "i"),
+    ('й', "y"),
+    ('к', "k"),
+    ('л', "l"),
+    ('м', "m"),
+    ('н', "n"),
+    ('о', "o"),
+    ('п', "p"),
+    ('р', "r"),
+    ('с', "s"),
+    ('т', "t"),
+    ('у', "u"),
+    ('ф', "f"),
+    ('х', "kh"),
+    ('ц', "ts"),
+    ('ч', "ch"),
+    ('ш', "sh"),
+    ('щ', "shch"),
+    ('ъ', ""),
+    ('ы', "y"),
+    ('ь', ""),
+    ('э', "e"),
+    ('ю', "yu"),
+    ('я', "ya"),
+];
+
+// LATIN_ENDINGS are matched at the end of the word before the rest of the rules
+const LATIN_ENDINGS: [(&str, &str); 6] = [
+    ("skiy", "ский"),
+    ("skii", "ский"),
+    ("sky", "ский"),
+    ("iy", "ий"),
+    ("oy", "ой"),
+    ("y", "ий"),
+];
+
+// LATIN is ordered by the length of the letter combinations, the longest go first
+const LATIN: [(&str, &str); 38] = [
+    ("shch", "щ"),
+    ("sch", "щ"),
+    ("zh", "ж"),
+    ("kh", "х"),
+    ("ts", "ц"),
+    ("ch", "ч"),
+    ("sh", "ш"),
+    ("yo", "ё"),
+    ("yu", "ю"),
+    ("ya", "я"),
+    ("ye", "е"),
+    ("ph", "ф"),
+    ("a", "а"),
+    ("b", "б"),
+    ("c", "к"),
+    ("d", "д"),
+    ("e", "е"),
+    ("f", "ф"),
+    ("g", "г"),
+    ("h", "х"),
+    ("i", "и"),
+    ("j", "й"),
+    ("k", "к"),
+    ("l", "л"),
+    ("m", "м"),
+    ("n", "н"),
+    ("o", "о"),
+    ("p", "п"),
+    ("q", "к"),
+    ("r", "р"),
+    ("s", "с"),
+    ("t", "т"),
+    ("u", "у"),
+    ("v", "в"),
+    ("w", "в"),
+    ("x", "кс"),
+    ("y", "ы"),
+    ("z", "з"),
+];
+
+// to_latin transliterates the lowercase Cyrillic text, the rest of the chars are kept as is
+pub fn to_latin(text: &str) -> String {
+    text.chars()
+        .map(|c| match CYRILLIC.iter().find(|(cyr, _)| *cyr == c) {
+            Some((_, lat)) => lat.to_string(),
+            None => c.to_string(),
+        })
+        .collect()
+}
+
+// to_cyrillic transliterates the lowercase Latin text, the rest of the chars are kept as is
+pub fn to_cyrillic(text: &str) -> String {
+    text.split_inclusive(|c: char| !c.is_ascii_alphabetic())
+        .map(word_to_cyrillic)
+        .collect()
+}
+
+fn word_to_cyrillic(chunk: &str) -> String {
+    let word_len = chunk
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(chunk.len());
+    let (word, rest) = chunk.split_at(word_len);
+
+    let (mut word, ending) = match LATIN_ENDINGS
+        .iter()
+        .find(|(lat, _)| word.len() > lat.len() && word.ends_with(lat))
+    {
+        Some((lat, cyr)) => (&word[..word.len() - lat.len()], *cyr),
+        None => (word, ""),
+    };
+
+    let mut result = String::new();
+    while !word.is_empty() {
+        match LATIN.iter().find(|(lat, _)| word.starts_with(lat)) {
+            Some((lat, cyr)) => {
+                result.push_str(cyr);
+                word = &word[lat.len()..];
+            }
+            None => {
+                // not reachable for the ascii letters
+                let c = word.chars().next().unwrap();
+                result.push(c);
+                word = &word[c.len_utf8()..];
+            }
+        }
+    }
+
+    result + ending + rest
+}
+
+// variants returns the term itself along with its transliteration into the other script
+pub fn variants(term: &str) -> Vec<String> {
+    let mut variants = vec![term.to_string()];
+    for variant in [to_latin(term), to_cyrillic(term)] {
+        if !variant.is_empty() && !variants.contains(&variant) {
+            variants.push(variant);
+        }
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_cyrillic_endings() {
+        assert_eq!(to_cyrillic("dostoevsky"), "достоевский");
+        assert_eq!(to_cyrillic("dostoevskiy"), "достоевский");
+        assert_eq!(to_cyrillic("dostoevskii"), "достоевский");
+        assert_eq!(to_cyrillic("tolstoy"), "толстой");
+        assert_eq!(to_cyrillic("gorkiy"), "горкий");
+    }
+
+    #[test]
+    fn to_cyrillic_short_words_keep_letters() {
+        // the ending shouldn't be the whole word
+        assert_eq!(to_cyrillic("y"), "ы");
+        assert_eq!(to_cyrillic("boy"), "бой");
+        // the shorter "y" ending applies instead
+        assert_eq!(to_cyrillic("sky"), "ский");
+    }
+
+    #[test]
+    fn to_cyrillic_combinations_and_separators() {
+        assert_eq!(to_cyrillic("shchedrin"), "щедрин");
+        assert_eq!(to_cyrillic("chekhov"), "чехов");
+        assert_eq!(to_cyrillic("zhukovsky, vasily"), "жуковский, василий");
+        assert_eq!(to_cyrillic("fyodor 2"), "фёдор 2");
+    }
+
+    #[test]
+    fn to_latin_letters() {
+        assert_eq!(to_latin("достоевский"), "dostoevskiy");
+        assert_eq!(to_latin("щедрин"), "shchedrin");
+        assert_eq!(to_latin("объём"), "obyom");
+        assert_eq!(to_latin("war 1812"), "war 1812");
+    }
+
+    #[test]
+    fn variants_skip_duplicates() {
+        assert_eq!(variants("толстой"), vec!["толстой", "tolstoy"]);
+        assert_eq!(variants("tolstoy"), vec!["tolstoy", "толстой"]);
+        assert_eq!(variants("1812"), vec!["1812"]);
+    }
+}
@@ -0,0 +1,20 @@
+// The built-in web UI, the static assets are embedded into the binary
+
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{Html, IntoResponse};
+
+const INDEX_HTML: &str = include_str!("ui/index.html");
+const APP_JS: &str = include_str!("ui/app.js");
+const STYLE_CSS: &str = include_str!("ui/style.css");
+
+pub async fn index_handler() -> impl IntoResponse {
+    Html(INDEX_HTML)
+}
+
+pub async fn app_js_handler() -> impl IntoResponse {
+    ([(CONTENT_TYPE, "text/javascript; charset=utf-8")], APP_JS)
+}
+
+pub async fn style_css_handler() -> impl IntoResponse {
+    ([(CONTENT_TYPE, "text/css; charset=utf-8")], STYLE_CSS)
+}
@@ -0,0 +1,298 @@
+// The built-in UI of the books search on top of the JSON API
+
+const PAGE_SIZE = 50;
+const FORMATS = ["epub", "azw3", "mobi"];
+const THUMBNAIL_SIZE = 96;
+const COVER_SIZE = 300;
+
+const $ = (id) => document.getElementById(id);
+
+const state = {
+    request: null,
+    url: null,
+    cursor: null,
+    selected: new Map(),
+};
+
+function el(tag, text, className) {
+    const node = document.createElement(tag);
+    if (text !== undefined && text !== null) {
+        node.textContent = text;
+    }
+    if (className) {
+        node.className = className;
+    }
+    return node;
+}
+
+async function api(url, body) {
+    const options = body === undefined ? {} : {
+        method: "POST",
+        headers: {"Content-Type": "application/json"},
+        body: JSON.stringify(body),
+    };
+    const response = await fetch(url, options);
+    if (response.status === 404) {
+        return null;
+    }
+    if (!response.ok) {
+        const error = await response.json().catch(() => ({}));
+        throw new Error(error.error || response.statusText);
+    }
+    return response.json();
+}
+
+function status(text) {
+    $("status").textContent = text || "";
+}
+
+async function loadOptions() {
+    const langs = await api("/api/book/langs").catch(() => null);
+    for (const lang of langs || []) {
+        $("search-lang").append(new Option(lang, lang));
+    }
+
+    const libraries = await api("/api/library").catch(() => null);
+    if (libraries && libraries.length > 1) {
+        for (const library of libraries) {
+            $("search-library").append(new Option(library.name || library.library, library.library));
+        }
+        $("search-library").hidden = false;
+    }
+}
+
+function filters() {
+    const request = {library: $("search-library").value};
+    if ($("search-lang").value) {
+        request.langs = [$("search-lang").value];
+    }
+    return request;
+}
+
+async function search(event) {
+    event.preventDefault();
+    const mode = $("search-mode").value;
+    const text = $("search-text").value.trim();
+    if (!text) {
+        return;
+    }
+    location.hash = "";
+
+    if (mode === "author") {
+        return searchAuthors(text);
+    }
+    const request = filters();
+    request[mode] = text;
+    const url = {query: "/api/book/find", title: "/api/book/search", series: "/api/book/series"}[mode];
+    startBooks(url, request);
+}
+
+async function searchAuthors(text) {
+    $("list").replaceChildren();
+    $("more").hidden = true;
+    status("Searching...");
+    try {
+        const authors = await api("/api/author/search", {author: text, limit: 100, library: $("search-library").value});
+        if (!authors || authors.length === 0) {
+            return status("No authors found");
+        }
+        status(`${authors.length} authors`);
+        for (const author of authors) {
+            const item = el("li");
+            const link = el("a", author.name, "title");
+            link.href = "#";
+            link.onclick = (e) => {
+                e.preventDefault();
+                startBooks("/api/book/search", {...filters(), authors: [author.name]});
+            };
+            const details = el("div", null, "details");
+            details.append(link, el("div", `${author.count} books`, "meta"));
+            item.append(details);
+            $("list").append(item);
+        }
+    } catch (e) {
+        status(e.message);
+    }
+}
+
+function startBooks(url, request) {
+    state.url = url;
+    state.request = {...request, limit: PAGE_SIZE, envelope: true};
+    state.cursor = null;
+    $("list").replaceChildren();
+    loadBooks();
+}
+
+async function loadBooks() {
+    $("more").hidden = true;
+    status("Searching...");
+    const request = {...state.request};
+    if (state.cursor) {
+        request.cursor = state.cursor;
+    }
+    try {
+        const page = await api(state.url, request);
+        if (!page || page.total === 0) {
+            return status("No books found");
+        }
+        status(`${page.total} books`);
+        for (const hit of page.hits) {
+            $("list").append(bookItem(hit));
+        }
+        state.cursor = page.next_cursor;
+        $("more").hidden = !state.cursor;
+    } catch (e) {
+        status(e.message);
+    }
+}
+
+// cover returns the cover thumbnail image removed when the book has no cover
+function cover(id, size) {
+    const img = el("img", null, "cover");
+    img.loading = "lazy";
+    img.alt = "";
+    img.src = `/api/book/${encodeURIComponent(id)}/cover?size=${size}`;
+    img.onerror = () => img.remove();
+    return img;
+}
+
+function seriesText(book) {
+    if (!book.series) {
+        return "";
+    }
+    return book.ser_no ? `${book.series} [${book.ser_no}]` : book.series;
+}
+
+function bookItem(hit) {
+    const book = hit.book;
+    const item = el("li");
+
+    const check = el("input");
+    check.type = "checkbox";
+    check.checked = state.selected.has(hit.id);
+    check.onchange = () => {
+        if (check.checked) {
+            state.selected.set(hit.id, book.title);
+        } else {
+            state.selected.delete(hit.id);
+        }
+        updateSelection();
+    };
+
+    const details = el("div", null, "details");
+    const link = el("a", book.title, "title");
+    link.href = `#book/${hit.id}`;
+    const meta = [(book.authors || []).join(", "), seriesText(book), book.lang, book.ext]
+        .filter(Boolean)
+        .join(" · ");
+    details.append(link, el("div", meta, "meta"));
+
+    item.append(check);
+    if (book.ext === "fb2") {
+        item.append(cover(hit.id, THUMBNAIL_SIZE));
+    }
+    item.append(details);
+    return item;
+}
+
+function updateSelection() {
+    const count = state.selected.size;
+    $("selection").hidden = count === 0;
+    $("selection-count").textContent = `${count} selected`;
+}
+
+function downloadArchive() {
+    const params = new URLSearchParams();
+    for (const id of state.selected.keys()) {
+        params.append("id", id);
+    }
+    if ($("archive-format").value) {
+        params.append("format", $("archive-format").value);
+    }
+    location.href = `/api/book/archive?${params}`;
+}
+
+async function showBook(id) {
+    $("results").hidden = true;
+    $("book").hidden = false;
+    $("book-title").textContent = "Loading...";
+    $("book-authors").textContent = "";
+    $("book-cover").replaceChildren();
+    $("book-info").replaceChildren();
+    $("book-annotation").replaceChildren();
+    $("book-downloads").replaceChildren();
+
+    let result;
+    try {
+        result = await api(`/api/book/${encodeURIComponent(id)}`);
+    } catch (e) {
+        result = null;
+    }
+    if (!result) {
+        $("book-title").textContent = "Book not found";
+        return;
+    }
+
+    const book = result.book;
+    const metadata = result.metadata || {};
+    $("book-title").textContent = book.title;
+    $("book-authors").textContent = (book.authors || []).join(", ");
+
+    if (metadata.cover_url) {
+        $("book-cover").append(cover(id, COVER_SIZE));
+    }
+
+    const info = [
+        ["Series", seriesText(book)],
+        ["Genres", (book.genres || []).join(", ")],
+        ["Language", book.lang],
+        ["Added", book.date],
+        ["Size", book.file_size ? `${Math.round(book.file_size / 1024)} KB` : ""],
+        ["Keywords", (book.keywords || []).join(", ")],
+        ["Translators", (metadata.translators || []).join(", ")],
+        ["Publisher", [metadata.publisher, metadata.year].filter(Boolean).join(", ")],
+        ["ISBN", metadata.isbn],
+        ["Library", book.library],
+    ];
+    for (const [name, value] of info.filter(([, value]) => value)) {
+        $("book-info").append(el("dt", name), el("dd", value));
+    }
+    for (const paragraph of (metadata.annotation || "").split("\n").filter(Boolean)) {
+        $("book-annotation").append(el("p", paragraph));
+    }
+
+    const download = `/api/book/${encodeURIComponent(id)}/download`;
+    const original = el("a", (book.ext || "file").toUpperCase());
+    original.href = download;
+    $("book-downloads").append(original);
+    if (book.ext === "fb2") {
+        for (const format of FORMATS) {
+            const link = el("a", format.toUpperCase());
+            link.href = `${download}?format=${format}`;
+            $("book-downloads").append(link);
+        }
+    }
+}
+
+function route() {
+    const match = location.hash.match(/^#book\/(.+)$/);
+    if (match) {
+        showBook(decodeURIComponent(match[1]));
+    } else {
+        $("book").hidden = true;
+        $("results").hidden = false;
+    }
+}
+
+$("search-form").addEventListener("submit", search);
+$("more").addEventListener("click", loadBooks);
+$("archive-download").addEventListener("click", downloadArchive);
+$("selection-clear").addEventListener("click", () => {
+    state.selected.clear();
+    document.querySelectorAll("#list input[type=checkbox]").forEach((c) => c.checked = false);
+    updateSelection();
+});
+window.addEventListener("hashchange", route);
+
+loadOptions();
+route();